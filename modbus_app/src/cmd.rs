@@ -1,22 +1,130 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use getset::Getters;
 use std::path::PathBuf;
 
+use crate::config_manager::modbus_variables::{DataType, ModbusStorage};
+use crate::identify::Level;
+use crate::scan::Probe;
 
-pub fn get_path()->PathBuf {
-    let args = Args::parse();
-    let home_dir: String = std::env::var("HOME").unwrap();
-    match args.file_path {
-        Some(path) => path,
-        None => PathBuf::from(home_dir)
-            .join(".config")
-            .join("simple_modbusclient")
-            .join("config.yaml"),
-    }   
+impl Args {
+    pub fn get_path(&self) -> PathBuf {
+        let home_dir: String = std::env::var("HOME").unwrap();
+        match &self.file_path {
+            Some(path) => path.to_owned(),
+            None => PathBuf::from(home_dir)
+                .join(".config")
+                .join("simple_modbusclient")
+                .join("config.yaml"),
+        }
+    }
 }
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Getters)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
     #[arg(short, long)]
-    file_path: Option<PathBuf>, 
-}
\ No newline at end of file
+    file_path: Option<PathBuf>,
+    #[command(subcommand)]
+    #[get = "pub"]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Запуск встроенного симулятора Modbus TCP устройства
+    Simulator {
+        /// Адрес и порт для входящих подключений
+        #[arg(short, long, default_value = "127.0.0.1:5502")]
+        listen: String,
+        /// Файл с начальными значениями регистров
+        #[arg(short, long)]
+        data: Option<PathBuf>,
+    },
+    /// Шлюз Modbus TCP - RTU к последовательному каналу из конфигурации
+    Gateway {
+        /// Адрес и порт для входящих подключений клиентов Modbus TCP
+        #[arg(short, long, default_value = "0.0.0.0:502")]
+        listen: String,
+        /// Имя канала Uart, если в конфигурации несколько каналов
+        #[arg(long)]
+        channel: Option<String>,
+    },
+    /// Чтение идентификации устройства (FC 43/14)
+    Identify {
+        /// Адрес устройства
+        #[arg(long, default_value_t = 1)]
+        unit_id: u8,
+        /// Набор объектов: basic, regular или extended. По умолчанию читается
+        /// наибольший набор, который поддерживает устройство
+        #[arg(long, conflicts_with = "object")]
+        level: Option<Level>,
+        /// Номер отдельного объекта для чтения
+        #[arg(long)]
+        object: Option<u8>,
+        /// Имя канала, если в конфигурации несколько каналов
+        #[arg(long)]
+        channel: Option<String>,
+    },
+    /// Поиск устройств на канале перебором адресов
+    Scan {
+        /// Первый проверяемый адрес устройства, 1..247
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=247))]
+        from: u8,
+        /// Последний проверяемый адрес устройства, 1..247
+        #[arg(long, default_value_t = 247, value_parser = clap::value_parser!(u8).range(1..=247))]
+        to: u8,
+        /// Проверочный запрос: holding (чтение регистра 0) или server-id (FC 17,
+        /// только на последовательной линии)
+        #[arg(long, default_value = "holding")]
+        probe: Probe,
+        /// Время ожидания ответа каждого адреса в секундах
+        #[arg(long, default_value_t = 0.1)]
+        timeout: f64,
+        /// Имя канала, если в конфигурации несколько каналов
+        #[arg(long)]
+        channel: Option<String>,
+    },
+    /// Вывод значений диапазона адресов устройства таблицей
+    Dump {
+        /// Область памяти: do (coil, 0x), di (1x), ai (input, 3x), ao (holding, 4x)
+        #[arg(long)]
+        storage: ModbusStorage,
+        /// Первый адрес диапазона
+        #[arg(long, default_value_t = 0)]
+        start: u16,
+        /// Количество адресов
+        #[arg(long)]
+        count: u16,
+        /// Адрес устройства
+        #[arg(long, default_value_t = 1)]
+        unit_id: u8,
+        /// Имя канала, если в конфигурации несколько каналов
+        #[arg(long)]
+        channel: Option<String>,
+    },
+    /// Проверка файла конфигурации с указанием строки и столбца каждой ошибки
+    Validate,
+    /// Запись значения переменной в устройство
+    Write {
+        /// Имя переменной из конфигурации
+        #[arg(long, conflicts_with_all = ["storage", "address"])]
+        name: Option<String>,
+        /// Область памяти для записи по адресу: do (coil, 0x), ao (holding, 4x)
+        #[arg(long, requires = "address")]
+        storage: Option<ModbusStorage>,
+        /// Адрес для записи без описания переменной в конфигурации
+        #[arg(long, requires = "storage")]
+        address: Option<u16>,
+        /// Адрес устройства для записи по адресу
+        #[arg(long, default_value_t = 1)]
+        unit_id: u8,
+        /// Тип данных для записи по адресу
+        #[arg(long)]
+        data_type: Option<DataType>,
+        /// Имя канала для записи по адресу, если в конфигурации несколько каналов
+        #[arg(long, conflicts_with = "name")]
+        channel: Option<String>,
+        /// Записываемое значение
+        value: String,
+    },
+}
@@ -1,48 +1,225 @@
-use std::{net::TcpStream, time::Duration};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
 
 use getset::Getters;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use serialport::{ClearBuffer, DataBits, Parity, SerialPort, StopBits};
+
+use crate::connection::Backoff;
+use crate::task::ProtocolType;
 #[derive(Debug, Deserialize, Clone)]
 /// Структура описывает конфигурацию соединения с клиентом modbus
 pub struct ChannelConfig {
-    /// Адрес устройства
+    /// Имя канала, на которое ссылаются переменные; по умолчанию адрес канала.
+    /// При публикации в MQTT обязательно, так как входит в топики
+    pub name: Option<String>,
+    /// Адрес устройства, по умолчанию 127.0.0.1
     pub host: Option<String>,
     /// порт устройства
-    pub port: Option<u32>,
-    /// UART device path
-    //path: Option<String>,
-    /// Настройка скорости приема передачи в бот
-    pub baud_rate: Option<f64>,
+    pub port: Option<u16>,
+    /// Протокол обмена, по умолчанию Tcp
+    pub protocol: Option<ProtocolType>,
+    /// UART device path, по умолчанию /dev/ttyUSB0
+    pub path: Option<String>,
+    /// Настройка скорости приема передачи в бод, по умолчанию 9600
+    pub baud_rate: Option<u32>,
+    /// Контроль четности: none (n), even (e), odd (o), по умолчанию none
+    #[serde(default, deserialize_with = "parity")]
+    pub parity: Option<Parity>,
+    /// Количество бит данных: 5, 6, 7, 8, по умолчанию 8
+    #[serde(default, deserialize_with = "data_bits")]
+    pub data_bits: Option<DataBits>,
+    /// Количество стоп-бит: 1, 2, по умолчанию 1
+    #[serde(default, deserialize_with = "stop_bits")]
+    pub stop_bits: Option<StopBits>,
+    /// Время ожидания ответа в секундах, по умолчанию 0.3
     pub timeout: Option<f64>,
+    /// Максимальный разрыв адресов, при котором переменные читаются одним запросом
+    pub max_gap: Option<u16>,
+    /// Интервал опроса переменных канала в секундах, если он не задан в переменной или группе
+    pub poll_interval: Option<f64>,
+    /// Начальная пауза перед повторным подключением в секундах, по умолчанию 0.5
+    pub backoff_min: Option<f64>,
+    /// Наибольшая пауза перед повторным подключением в секундах, по умолчанию 30
+    pub backoff_max: Option<f64>,
+    /// Доля случайного сокращения паузы (0..1), по умолчанию 0.2
+    pub backoff_jitter: Option<f64>,
+    /// Количество запросов Modbus TCP, отправляемых без ожидания ответов, по умолчанию 1
+    pub window: Option<usize>,
+    /// Номер первого регистра в адресах Modicon переменных: 1 (40001 - первый
+    /// регистр) или 0 (40000 - первый регистр), по умолчанию 1
+    pub address_base: Option<u16>,
 }
 
 impl From<ChannelConfig> for ChannelTcp {
     fn from(value: ChannelConfig) -> Self {
+        let timeout = value.timeout();
         Self {
             host: match value.host {
                 Some(host) => host,
                 None => "127.0.0.1".to_string(),
             },
-            port: match value.port {
-                Some(port) => port,
-                None => 502,
-            },
-            timeout: match value.timeout {
-                Some(timeout) => Duration::from_secs_f64(timeout),
-                None => Duration::from_millis(300),
+            port: value.port.unwrap_or(502),
+            timeout,
+        }
+    }
+}
+impl From<ChannelConfig> for ChannelRtu {
+    fn from(value: ChannelConfig) -> Self {
+        let timeout = value.timeout();
+        Self {
+            path: match value.path {
+                Some(path) => path,
+                None => "/dev/ttyUSB0".to_string(),
             },
+            baud_rate: value.baud_rate.unwrap_or(9600),
+            parity: value.parity.unwrap_or(Parity::None),
+            data_bits: value.data_bits.unwrap_or(DataBits::Eight),
+            stop_bits: value.stop_bits.unwrap_or(StopBits::One),
+            timeout,
         }
     }
 }
+
+fn parity<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Parity>, D::Error> {
+    let Some(parity) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    match &parity.to_lowercase()[..] {
+        "none" | "n" => Ok(Some(Parity::None)),
+        "even" | "e" => Ok(Some(Parity::Even)),
+        "odd" | "o" => Ok(Some(Parity::Odd)),
+        _ => Err(serde::de::Error::custom(format!(
+            "неизвестный контроль четности {parity}, ожидается none, even или odd"
+        ))),
+    }
+}
+
+fn data_bits<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DataBits>, D::Error> {
+    Option::<u8>::deserialize(deserializer)?
+        .map(|bits| {
+            DataBits::try_from(bits).map_err(|_| {
+                serde::de::Error::custom(format!(
+                    "недопустимое количество бит данных {bits}, ожидается 5, 6, 7 или 8"
+                ))
+            })
+        })
+        .transpose()
+}
+
+fn stop_bits<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<StopBits>, D::Error> {
+    Option::<u8>::deserialize(deserializer)?
+        .map(|bits| {
+            StopBits::try_from(bits).map_err(|_| {
+                serde::de::Error::custom(format!(
+                    "недопустимое количество стоп-бит {bits}, ожидается 1 или 2"
+                ))
+            })
+        })
+        .transpose()
+}
+
 pub trait Connect {
     type Output;
     fn connect(&self) -> Self::Output;
 }
+
+/// Асинхронный аналог Connect для работы в среде tokio
+#[cfg(feature = "async")]
+pub trait AsyncConnect {
+    type Output;
+    fn connect_async(&self) -> impl std::future::Future<Output = Self::Output> + Send;
+}
+
+/// Канал связи, поверх которого выполняется обмен modbus
+pub trait Transport: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Transport for T {}
+
+impl ChannelConfig {
+    pub fn protocol_type(&self) -> ProtocolType {
+        self.protocol.to_owned().unwrap_or(ProtocolType::Tcp)
+    }
+
+    pub fn name(&self) -> String {
+        self.name.to_owned().unwrap_or_else(|| self.address())
+    }
+
+    /// Паузы между попытками подключения к каналу
+    pub fn backoff(&self) -> Backoff {
+        let seconds = |value: Option<f64>, default: Duration| {
+            value
+                .and_then(|value| Duration::try_from_secs_f64(value).ok())
+                .unwrap_or(default)
+        };
+        Backoff::new(
+            seconds(self.backoff_min, Duration::from_millis(500)),
+            seconds(self.backoff_max, Duration::from_secs(30)),
+            self.backoff_jitter.unwrap_or(0.2),
+        )
+    }
+
+    /// Время ожидания ответа; недопустимое значение, отмеченное при проверке
+    /// конфигурации, заменяется значением по умолчанию
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+            .and_then(|timeout| Duration::try_from_secs_f64(timeout).ok())
+            .filter(|timeout| !timeout.is_zero())
+            .unwrap_or(Duration::from_millis(300))
+    }
+
+    /// Окно одновременно ожидающих ответа запросов; в RTU ответы не различить,
+    /// поэтому запросы всегда выполняются по одному
+    pub fn window(&self) -> usize {
+        match self.protocol_type() {
+            ProtocolType::Tcp => self.window.unwrap_or(1),
+            ProtocolType::Uart => 1,
+        }
+    }
+
+    /// Номер первого регистра в адресах Modicon
+    pub fn address_base(&self) -> u16 {
+        self.address_base.unwrap_or(1)
+    }
+
+    /// Адрес канала связи для сообщений пользователю
+    pub fn address(&self) -> String {
+        match self.protocol_type() {
+            ProtocolType::Tcp => ChannelTcp::from(self.to_owned()).url(),
+            ProtocolType::Uart => ChannelRtu::from(self.to_owned()).path,
+        }
+    }
+
+    /// Подключение для однократных команд, ошибка содержит адрес канала
+    pub fn open(&self) -> Result<Box<dyn Transport>, Box<dyn std::error::Error>> {
+        self.connect().map_err(|err| {
+            format!(
+                "Ошибка установки соединения с клиентом {}: {err}",
+                self.address()
+            )
+            .into()
+        })
+    }
+}
+
+/// Подключение к каналу связи согласно протоколу из конфигурации
+impl Connect for ChannelConfig {
+    type Output = Result<Box<dyn Transport>, Box<dyn std::error::Error>>;
+    fn connect(&self) -> Self::Output {
+        Ok(match self.protocol_type() {
+            ProtocolType::Tcp => Box::new(ChannelTcp::from(self.to_owned()).connect()?),
+            ProtocolType::Uart => Box::new(ChannelRtu::from(self.to_owned()).connect()?),
+        })
+    }
+}
 #[derive(Getters)]
 #[get = "pub"]
 pub struct ChannelTcp {
     host: String,
-    port: u32,
+    port: u16,
     timeout: Duration,
 }
 
@@ -56,9 +233,196 @@ impl Connect for ChannelTcp {
     type Output = Result<TcpStream, Box<dyn std::error::Error>>;
     fn connect(&self) -> Self::Output {
         let stream = TcpStream::connect(self.url())?;
-        stream.set_nonblocking(true)?;
-        stream.set_read_timeout(Some(self.timeout.clone()))?;
-        stream.set_write_timeout(Some(self.timeout.clone()))?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        Ok(stream)
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncConnect for ChannelTcp {
+    type Output = Result<tokio::net::TcpStream, Box<dyn std::error::Error + Send + Sync>>;
+    async fn connect_async(&self) -> Self::Output {
+        let stream = tokio::time::timeout(self.timeout, tokio::net::TcpStream::connect(self.url()))
+            .await
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+        stream.set_nodelay(true)?;
         Ok(stream)
     }
 }
+
+#[derive(Getters)]
+#[get = "pub"]
+pub struct ChannelRtu {
+    path: String,
+    baud_rate: u32,
+    parity: Parity,
+    data_bits: DataBits,
+    stop_bits: StopBits,
+    timeout: Duration,
+}
+
+impl ChannelRtu {
+    /// Минимальная пауза между кадрами RTU: 3.5 символа,
+    /// но не менее 1.75 мс для скоростей выше 19200 бод
+    pub fn silence(&self) -> Duration {
+        if self.baud_rate > 19200 {
+            return Duration::from_micros(1750);
+        }
+        let parity_bits = match self.parity {
+            Parity::None => 0,
+            Parity::Odd | Parity::Even => 1,
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        let char_bits = 1 + u8::from(self.data_bits) as u32 + parity_bits + stop_bits;
+        Duration::from_secs_f64(3.5 * char_bits as f64 / self.baud_rate.max(1) as f64)
+    }
+}
+
+impl Connect for ChannelRtu {
+    type Output = Result<RtuStream, Box<dyn std::error::Error>>;
+    fn connect(&self) -> Self::Output {
+        let port = serialport::new(self.path.as_str(), self.baud_rate)
+            .parity(self.parity)
+            .data_bits(self.data_bits)
+            .stop_bits(self.stop_bits)
+            .timeout(self.timeout)
+            .open()?;
+        Ok(RtuStream::new(port, self.silence()))
+    }
+}
+
+/// Последовательный порт, выдерживающий паузу между кадрами RTU
+pub struct RtuStream {
+    port: Box<dyn SerialPort>,
+    silence: Duration,
+    last_activity: Instant,
+}
+
+impl RtuStream {
+    pub fn new(port: Box<dyn SerialPort>, silence: Duration) -> Self {
+        Self {
+            port,
+            silence,
+            last_activity: Instant::now(),
+        }
+    }
+}
+
+impl Read for RtuStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let size = self.port.read(buf)?;
+        self.last_activity = Instant::now();
+        Ok(size)
+    }
+}
+
+impl Write for RtuStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // Новый кадр можно начинать только после паузы на линии
+        let elapsed = self.last_activity.elapsed();
+        if elapsed < self.silence {
+            std::thread::sleep(self.silence - elapsed);
+        }
+        // Остатки предыдущего ответа не должны попасть в следующий
+        self.port.clear(ClearBuffer::Input)?;
+        self.port.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.port.flush()?;
+        self.last_activity = Instant::now();
+        Ok(())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::modbus_manager::exchange;
+    use crate::task::{CommandType, Task};
+    use serialport::TTYPort;
+
+    #[test]
+    fn rtu_silence() {
+        let channel = ChannelRtu::from(ChannelConfig {
+            name: None,
+            host: None,
+            port: None,
+            protocol: Some(ProtocolType::Uart),
+            path: None,
+            baud_rate: Some(9600),
+            parity: Some(Parity::Even),
+            data_bits: Some(DataBits::Eight),
+            stop_bits: Some(StopBits::One),
+            timeout: None,
+            max_gap: None,
+            poll_interval: None,
+            backoff_min: None,
+            backoff_max: None,
+            backoff_jitter: None,
+            window: None,
+            address_base: None,
+        });
+        assert_eq!(channel.silence().as_micros(), 4010);
+        let channel = ChannelRtu {
+            baud_rate: 115200,
+            ..channel
+        };
+        assert_eq!(channel.silence(), Duration::from_micros(1750));
+    }
+
+    #[test]
+    fn rtu_poll_over_pty() -> Result<(), Box<dyn std::error::Error>> {
+        let (mut master, slave) = TTYPort::pair()?;
+        let path = slave.name().ok_or("pty without name")?;
+        let channel = ChannelRtu::from(ChannelConfig {
+            name: None,
+            host: None,
+            port: None,
+            protocol: Some(ProtocolType::Uart),
+            path: Some(path),
+            baud_rate: Some(19200),
+            parity: None,
+            data_bits: None,
+            stop_bits: None,
+            timeout: Some(1.0),
+            max_gap: None,
+            poll_interval: None,
+            backoff_min: None,
+            backoff_max: None,
+            backoff_jitter: None,
+            window: None,
+            address_base: None,
+        });
+        let mut stream = channel.connect()?;
+        master.set_timeout(Duration::from_secs(1))?;
+        // Устройство возвращает master, чтобы pty не закрылся до чтения ответа
+        let device = std::thread::spawn(move || -> std::io::Result<(TTYPort, Vec<u8>)> {
+            let mut request = [0u8; 8];
+            master.read_exact(&mut request)?;
+            master.write_all(&[0x01, 0x03, 0x02, 0x01, 0x2C, 0xB8, 0x09])?;
+            Ok((master, request.to_vec()))
+        });
+        let mut task = Task::new(
+            1,
+            1,
+            ProtocolType::Uart,
+            CommandType::ReadHoldingRegisters,
+            10,
+            1,
+            vec![],
+        );
+        let result = exchange(&mut stream, &mut task)?;
+        let (_master, request) = device.join().map_err(|_| "device thread panicked")??;
+        assert_eq!(
+            request,
+            vec![0x01, 0x03, 0x00, 0x0A, 0x00, 0x01, 0xA4, 0x08]
+        );
+        assert_eq!(result, Some(vec![300]));
+        Ok(())
+    }
+}
@@ -1,22 +1,385 @@
 pub mod channel_config;
-mod modbus_variables;
+pub mod modbus_variables;
+#[cfg(feature = "mqtt")]
+pub mod mqtt_config;
+pub mod simulator_config;
+pub mod validation;
 use getset::Getters;
 use serde::Deserialize;
 use std::path::PathBuf;
 
-use self::{channel_config::ChannelConfig, modbus_variables::ConfigItem};
+use self::{
+    channel_config::ChannelConfig,
+    modbus_variables::{ConfigItem, PollGroup},
+    validation::{Diagnostic, Diagnostics},
+};
+use crate::error::ModbusError;
 
 #[derive(Debug, Deserialize, Getters)]
 #[get = "pub"]
 pub struct Config {
-    channel: ChannelConfig,
+    /// Единственный канал связи, сохранен для совместимости со старыми конфигурациями
+    #[serde(default)]
+    #[getset(skip)]
+    channel: Option<ChannelConfig>,
+    /// Каналы связи, опрашиваемые одновременно
+    #[serde(default)]
+    channels: Vec<ChannelConfig>,
+    /// Группы опроса с общим интервалом
+    #[serde(default)]
+    groups: Vec<PollGroup>,
     variables: Vec<ConfigItem>,
+    /// Публикация значений переменных в брокер MQTT
+    #[cfg(feature = "mqtt")]
+    #[serde(default)]
+    #[getset(skip)]
+    mqtt: Option<mqtt_config::MqttConfig>,
 }
 
 impl Config {
     pub fn try_read_config_file(path: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
-        let open_file = std::fs::File::open(path)?;
-        let config: Self = serde_yaml::from_reader(open_file)?;
+        let source = std::fs::read_to_string(path)?;
+        Ok(Self::parse(&source).map_err(Diagnostics)?)
+    }
+
+    /// Разбирает и проверяет конфигурацию, возвращая все найденные ошибки
+    /// с их положением в тексте
+    pub fn parse(source: &str) -> Result<Self, Vec<Diagnostic>> {
+        let mut config: Self =
+            serde_yaml::from_str(source).map_err(|err| vec![Diagnostic::from(err)])?;
+        let diagnostics = validation::validate(&config, source);
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+        config.resolve_channels();
+        config.resolve_addresses();
+        config.resolve_poll_intervals();
         Ok(config)
     }
+
+    #[cfg(feature = "mqtt")]
+    pub fn mqtt(&self) -> Option<&mqtt_config::MqttConfig> {
+        self.mqtt.as_ref()
+    }
+
+    /// Канал связи по имени, без имени - единственный канал конфигурации
+    pub fn find_channel(&self, name: Option<&str>) -> Result<&ChannelConfig, ModbusError> {
+        match name {
+            Some(name) => self
+                .channels
+                .iter()
+                .find(|channel| channel.name() == name)
+                .ok_or_else(|| ModbusError::Config(format!("канал {name} не описан"))),
+            None => match self.channels.as_slice() {
+                [channel] => Ok(channel),
+                [] => Err(ModbusError::Config("не описан ни один канал".to_string())),
+                _ => Err(ModbusError::Config(
+                    "описано несколько каналов, укажите имя канала".to_string(),
+                )),
+            },
+        }
+    }
+
+    /// Переменные, опрашиваемые через канал
+    pub fn channel_variables(&self, channel: &ChannelConfig) -> Vec<ConfigItem> {
+        let name = channel.name();
+        self.variables
+            .iter()
+            .filter(|item| item.channel.as_deref() == Some(name.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    /// Назначает канал каждой переменной. Имена каналов и ссылки на них
+    /// проверяются в validation, поэтому канал всегда находится
+    fn resolve_channels(&mut self) {
+        if let Some(channel) = self.channel.take() {
+            self.channels.insert(0, channel);
+        }
+        for index in 0..self.variables.len() {
+            let item = &self.variables[index];
+            if let Ok(channel) = self.find_channel(item.channel.as_deref()) {
+                self.variables[index].channel = Some(channel.name());
+            }
+        }
+    }
+
+    /// Заменяет адреса Modicon и IEC областью памяти и смещением с учетом
+    /// нумерации регистров канала переменной
+    fn resolve_addresses(&mut self) {
+        for index in 0..self.variables.len() {
+            let item = &self.variables[index];
+            let base = self
+                .find_channel(item.channel.as_deref())
+                .map(|channel| channel.address_base())
+                .unwrap_or(1);
+            if let Ok((storage, start)) = item.location(base) {
+                let item = &mut self.variables[index];
+                item.storage = Some(storage);
+                item.start = Some(start);
+                item.address = None;
+            }
+        }
+    }
+
+    /// Подставляет интервал группы или канала переменным без собственного интервала
+    fn resolve_poll_intervals(&mut self) {
+        for item in self.variables.iter_mut() {
+            let group_interval = self
+                .groups
+                .iter()
+                .find(|group| item.group.as_ref() == Some(&group.name))
+                .map(|group| group.poll_interval);
+            let channel_interval = self
+                .channels
+                .iter()
+                .find(|channel| item.channel.as_deref() == Some(channel.name().as_str()))
+                .and_then(|channel| channel.poll_interval);
+            item.poll_interval = item.poll_interval.or(group_interval).or(channel_interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::modbus_variables::{DataType, ModbusRequestItems, ModbusStorage};
+    use crate::task::ProtocolType;
+
+    const CONFIG: &str = "
+channel:
+  poll_interval: 2
+groups:
+  - name: alarms
+    poll_interval: 0.1
+variables:
+  - {storage: di, id: 1, unit_id: 1, name: alarm, start: 0, group: alarms}
+  - {storage: di, id: 2, unit_id: 1, name: door, start: 1, group: alarms, poll_interval: 0.5}
+  - {storage: ai, id: 3, unit_id: 1, name: counter, start: 0}
+";
+
+    #[test]
+    fn poll_intervals_are_inherited() -> Result<(), Box<dyn std::error::Error>> {
+        let config = Config::parse(CONFIG).map_err(validation::Diagnostics)?;
+        let intervals = config
+            .variables()
+            .iter()
+            .map(|item| item.poll_interval)
+            .collect::<Vec<_>>();
+        assert_eq!(intervals, vec![Some(0.1), Some(0.5), Some(2.0)]);
+        Ok(())
+    }
+
+    /// Пути и положения ошибок конфигурации
+    fn located(source: &str) -> Vec<(String, Option<(usize, usize)>)> {
+        Config::parse(source)
+            .err()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|diagnostic| (diagnostic.path, diagnostic.location))
+            .collect()
+    }
+
+    #[test]
+    fn invalid_poll_intervals_are_located() {
+        let source = CONFIG
+            .replace("group: alarms}", "group: slow}")
+            .replace("poll_interval: 2", "poll_interval: 0")
+            .replace("poll_interval: 0.1", "poll_interval: -1")
+            .replace("poll_interval: 0.5", "poll_interval: .nan");
+        assert_eq!(
+            located(&source),
+            vec![
+                ("channel.poll_interval".to_string(), Some((3, 18))),
+                ("groups[0].poll_interval".to_string(), Some((6, 20))),
+                ("variables[0].group".to_string(), Some((8, 68))),
+                ("variables[1].poll_interval".to_string(), Some((9, 90))),
+            ]
+        );
+    }
+
+    const CHANNELS: &str = "
+channels:
+  - {name: boiler, host: 10.0.0.5}
+  - {name: pumps, protocol: Uart, path: /dev/ttyUSB1}
+variables:
+  - {storage: ai, id: 1, unit_id: 1, name: temperature, start: 0, channel: boiler}
+  - {storage: ai, id: 2, unit_id: 3, name: flow, start: 0, channel: pumps}
+";
+
+    #[test]
+    fn variables_are_bound_to_channels() -> Result<(), Box<dyn std::error::Error>> {
+        let config = Config::parse(CHANNELS).map_err(validation::Diagnostics)?;
+        let pumps = config.find_channel(Some("pumps"))?;
+        assert_eq!(pumps.address(), "/dev/ttyUSB1");
+        let names = config
+            .channel_variables(pumps)
+            .into_iter()
+            .map(|item| item.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["flow".to_string()]);
+        assert!(config.find_channel(None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn variable_channel_must_exist() {
+        assert_eq!(
+            located(&CHANNELS.replace("channel: pumps", "channel: fans")),
+            vec![("variables[1].channel".to_string(), Some((7, 69)))]
+        );
+        assert_eq!(
+            located(&CHANNELS.replace(", channel: pumps", "")),
+            vec![("variables[1]".to_string(), Some((7, 5)))]
+        );
+        assert_eq!(
+            located(&CHANNELS.replace("name: pumps", "name: boiler")),
+            vec![
+                ("channels[1].name".to_string(), Some((4, 12))),
+                ("variables[1].channel".to_string(), Some((7, 69))),
+            ]
+        );
+    }
+
+    #[test]
+    fn single_channel_is_default() -> Result<(), Box<dyn std::error::Error>> {
+        let config = Config::parse(CONFIG).map_err(validation::Diagnostics)?;
+        assert_eq!(config.channels().len(), 1);
+        assert_eq!(
+            config.variables()[0].channel.as_deref(),
+            Some("127.0.0.1:502")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn enum_aliases_are_accepted() -> Result<(), Box<dyn std::error::Error>> {
+        let config = Config::parse(
+            "
+channels:
+  - {name: line, protocol: rtu, path: /dev/ttyUSB0, parity: E, data_bits: 7, stop_bits: 2}
+variables:
+  - {storage: holding, id: 1, unit_id: 1, name: setpoint, start: 0, data_type: F32}
+  - {storage: 4x, id: 2, unit_id: 1, name: mode, start: 2, byte_order: cdab}
+  - {storage: coil, id: 3, unit_id: 1, name: pump, start: 0}
+  - {storage: discrete_input, id: 4, unit_id: 1, name: alarm, start: 0}
+",
+        )
+        .map_err(validation::Diagnostics)?;
+        assert_eq!(config.channels()[0].protocol_type(), ProtocolType::Uart);
+        let storages = config
+            .variables()
+            .iter()
+            .map(|item| item.storage)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            storages,
+            vec![
+                Some(ModbusStorage::AO),
+                Some(ModbusStorage::AO),
+                Some(ModbusStorage::DO),
+                Some(ModbusStorage::DI)
+            ]
+        );
+        assert_eq!(config.variables()[0].data_type, Some(DataType::F32));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_enum_values_are_rejected() {
+        for (source, line) in [
+            (
+                "variables:\n  - {storage: hr, id: 1, unit_id: 1, name: x, start: 0}",
+                2,
+            ),
+            (
+                "channels:\n  - name: line\n    protocol: rtu\n    parity: mark",
+                2,
+            ),
+            ("channels:\n  - {name: line, protocol: Udp}", 2),
+            (
+                "channels:\n  - {name: line, protocol: rtu, path: /dev/ttyUSB0, data_bits: 9}",
+                2,
+            ),
+            ("channels:\n  - {name: line, host: plc, port: 70000}", 2),
+        ] {
+            let diagnostics = Config::parse(source).unwrap_err();
+            assert_eq!(diagnostics.len(), 1, "{source}");
+            assert_eq!(
+                diagnostics[0].location.map(|(row, _)| row),
+                Some(line),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn addresses_are_resolved() -> Result<(), Box<dyn std::error::Error>> {
+        let config = Config::parse(
+            "
+channels:
+  - {name: plc, host: 10.0.0.5}
+  - {name: meter, host: 10.0.0.6, address_base: 0}
+variables:
+  - {id: 1, unit_id: 1, name: setpoint, address: 40001, channel: plc}
+  - {id: 2, unit_id: 1, name: flow, address: 300010, channel: plc}
+  - {id: 3, unit_id: 1, name: pump, address: 00017, channel: plc}
+  - {id: 4, unit_id: 1, name: alarm, address: '10005', channel: plc}
+  - {id: 5, unit_id: 1, name: mode, address: '%MW100', channel: plc}
+  - {id: 6, unit_id: 1, name: energy, address: 40000, channel: meter}
+  - {id: 7, unit_id: 1, name: state, address: '%IW7', channel: meter}
+",
+        )
+        .map_err(validation::Diagnostics)?;
+        let locations = config
+            .variables()
+            .iter()
+            .map(|item| (item.storage, item.start, item.address))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            vec![
+                (Some(ModbusStorage::AO), Some(0), None),
+                (Some(ModbusStorage::AI), Some(9), None),
+                (Some(ModbusStorage::DO), Some(16), None),
+                (Some(ModbusStorage::DI), Some(4), None),
+                (Some(ModbusStorage::AO), Some(100), None),
+                (Some(ModbusStorage::AO), Some(0), None),
+                (Some(ModbusStorage::AI), Some(7), None),
+            ]
+        );
+        // Неразрешенный адрес не превращается молча в регистр AI 0
+        let unresolved: ConfigItem =
+            serde_yaml::from_str("{id: 1, unit_id: 1, name: setpoint, address: 40001}")?;
+        assert!(matches!(
+            ModbusRequestItems::try_from(unresolved),
+            Err(ModbusError::Config(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_addresses_are_rejected() {
+        for (variable, path) in [
+            ("address: 50001", "variables[0]"),
+            ("address: 4001", "variables[0]"),
+            ("address: '%QW1'", "variables[0]"),
+            ("address: 40000", "variables[0].address"),
+            ("address: 465537", "variables[0].address"),
+            ("address: 40001, storage: ao", "variables[0].address"),
+            ("storage: ao", "variables[0]"),
+        ] {
+            let source = format!(
+                "channel: {{host: plc}}\nvariables:\n  - {{id: 1, unit_id: 1, name: x, {variable}}}"
+            );
+            let diagnostics = Config::parse(&source).unwrap_err();
+            assert_eq!(diagnostics.len(), 1, "{source}");
+            // Ошибки разбора содержат путь в тексте сообщения
+            assert!(
+                diagnostics[0].to_string().contains(&format!("{path}: ")),
+                "{}",
+                diagnostics[0]
+            );
+        }
+    }
 }
@@ -1,5 +1,17 @@
-use serde::Deserialize;
+use std::{str::FromStr, time::Duration};
 
+use serde::{Deserialize, Deserializer};
+
+use crate::error::ModbusError;
+use crate::task::CommandType;
+
+/// Интервал опроса переменных, для которых он не задан ни в переменной, ни в группе, ни в канале
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Область памяти устройства. В конфигурации допускаются имена di, do, ai, ao,
+/// их синонимы и обозначения Modicon 0x, 1x, 3x, 4x без учета регистра
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(try_from = "String")]
 pub enum ModbusStorage {
     DI,
     DO,
@@ -7,14 +19,299 @@ pub enum ModbusStorage {
     AO,
 }
 
-impl From<String> for ModbusStorage {
-    fn from(value: String) -> Self {
+impl FromStr for ModbusStorage {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
         match &value.to_lowercase()[..] {
-            "di" => ModbusStorage::DI,
-            "do" => ModbusStorage::DO,
-            "ai" => ModbusStorage::AI,
-            "ao" => ModbusStorage::AO,
-            _ => ModbusStorage::AI,
+            "do" | "0x" | "coil" | "coils" => Ok(ModbusStorage::DO),
+            "di" | "1x" | "discrete_input" | "discrete_inputs" => Ok(ModbusStorage::DI),
+            "ai" | "3x" | "input" | "input_register" | "input_registers" => Ok(ModbusStorage::AI),
+            "ao" | "4x" | "holding" | "holding_register" | "holding_registers" => {
+                Ok(ModbusStorage::AO)
+            }
+            _ => Err(format!(
+                "неизвестная область памяти {value}, ожидается do (coil, 0x), \
+                 di (discrete_input, 1x), ai (input, 3x) или ao (holding, 4x)"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for ModbusStorage {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl ModbusStorage {
+    /// Команда чтения соответствующей области памяти
+    pub fn read_command(&self) -> CommandType {
+        match self {
+            ModbusStorage::DO => CommandType::ReadCoilStatus,
+            ModbusStorage::DI => CommandType::ReadInputStatus,
+            ModbusStorage::AO => CommandType::ReadHoldingRegisters,
+            ModbusStorage::AI => CommandType::ReadInputRegisters,
+        }
+    }
+
+    /// Максимальное количество бит или регистров в одном запросе чтения
+    pub fn max_count(&self) -> u16 {
+        match self {
+            ModbusStorage::DO | ModbusStorage::DI => 2000,
+            ModbusStorage::AO | ModbusStorage::AI => 125,
+        }
+    }
+}
+
+/// Тип данных переменной
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum DataType {
+    Bool,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    U64,
+    F32,
+    F64,
+    /// ASCII строка, по два символа в регистре
+    String,
+    /// Двоично-десятичное число из 4 цифр
+    Bcd16,
+    /// Двоично-десятичное число из 8 цифр
+    Bcd32,
+}
+
+impl FromStr for DataType {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match &value.to_lowercase()[..] {
+            "bool" => DataType::Bool,
+            "i16" | "int16" => DataType::I16,
+            "u16" | "uint16" => DataType::U16,
+            "i32" | "int32" => DataType::I32,
+            "u32" | "uint32" => DataType::U32,
+            "i64" | "int64" => DataType::I64,
+            "u64" | "uint64" => DataType::U64,
+            "f32" | "float" | "float32" => DataType::F32,
+            "f64" | "double" | "float64" => DataType::F64,
+            "string" => DataType::String,
+            "bcd" | "bcd16" => DataType::Bcd16,
+            "bcd32" => DataType::Bcd32,
+            _ => Err(format!(
+                "неизвестный тип данных {value}, ожидается bool, i16, u16, i32, u32, \
+                 i64, u64, f32, f64, string, bcd16 или bcd32"
+            ))?,
+        })
+    }
+}
+
+impl TryFrom<String> for DataType {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl DataType {
+    /// Количество регистров, занимаемых значением
+    pub fn register_count(&self) -> u16 {
+        match self {
+            DataType::Bool | DataType::I16 | DataType::U16 | DataType::Bcd16 => 1,
+            DataType::I32 | DataType::U32 | DataType::F32 | DataType::Bcd32 => 2,
+            DataType::I64 | DataType::U64 | DataType::F64 => 4,
+            DataType::String => 1,
+        }
+    }
+}
+
+/// Порядок байт и слов многорегистровых значений,
+/// A - старший байт значения, D - младший (для 32 бит)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ByteOrder {
+    /// Старшее слово первым, старший байт слова первым
+    Abcd,
+    /// Младшее слово первым, старший байт слова первым
+    Cdab,
+    /// Старшее слово первым, младший байт слова первым
+    Badc,
+    /// Младшее слово первым, младший байт слова первым
+    Dcba,
+}
+
+impl FromStr for ByteOrder {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match &value.to_uppercase()[..] {
+            "ABCD" => Ok(ByteOrder::Abcd),
+            "CDAB" => Ok(ByteOrder::Cdab),
+            "BADC" => Ok(ByteOrder::Badc),
+            "DCBA" => Ok(ByteOrder::Dcba),
+            _ => Err(format!(
+                "неизвестный порядок байт {value}, ожидается ABCD, CDAB, BADC или DCBA"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for ByteOrder {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl ByteOrder {
+    /// Приводит регистры к порядку ABCD
+    pub fn normalize(&self, registers: &[u16]) -> Vec<u16> {
+        let mut result = self.swap_bytes(registers);
+        if matches!(self, ByteOrder::Cdab | ByteOrder::Dcba) {
+            result.reverse();
+        }
+        result
+    }
+
+    /// Меняет байты в каждом регистре для порядков BADC и DCBA, не меняя
+    /// порядок регистров. Для строк порядок слов не имеет смысла
+    pub fn swap_bytes(&self, registers: &[u16]) -> Vec<u16> {
+        let swap_bytes = matches!(self, ByteOrder::Badc | ByteOrder::Dcba);
+        registers
+            .iter()
+            .map(|register| {
+                if swap_bytes {
+                    register.swap_bytes()
+                } else {
+                    *register
+                }
+            })
+            .collect()
+    }
+}
+
+/// Линейное преобразование сырого значения в инженерное: raw * scale + offset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scaling {
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl Scaling {
+    /// Преобразование по двум точкам: raw_range[0] -> eng_range[0], raw_range[1] -> eng_range[1]
+    pub fn from_ranges(raw_range: [f64; 2], eng_range: [f64; 2]) -> Option<Self> {
+        let raw_span = raw_range[1] - raw_range[0];
+        if raw_span == 0.0 {
+            return None;
+        }
+        let scale = (eng_range[1] - eng_range[0]) / raw_span;
+        Some(Self {
+            scale,
+            offset: eng_range[0] - raw_range[0] * scale,
+        })
+    }
+
+    pub fn apply(&self, raw: f64) -> f64 {
+        raw * self.scale + self.offset
+    }
+
+    /// Обратное преобразование инженерного значения в сырое
+    pub fn unapply(&self, value: f64) -> f64 {
+        (value - self.offset) / self.scale
+    }
+}
+
+/// Адрес переменной в нотации Modicon (40001, 300010, 00017) или IEC 61131-3
+/// (%MW100, %IW5, %M17, %I3)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Address {
+    /// Номер регистра после цифры области памяти, отсчитывается от начала,
+    /// принятого в устройстве
+    Modicon { storage: ModbusStorage, number: u32 },
+    /// Смещение от нуля
+    Iec { storage: ModbusStorage, start: u16 },
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "неверный адрес {value}, ожидается 5 или 6 цифр Modicon (40001, 300010) \
+                 или адрес IEC (%MW100, %IW100, %M100, %I100)"
+            )
+        };
+        if let Some(iec) = value.strip_prefix('%') {
+            let upper = iec.to_uppercase();
+            let digits = upper.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+            let storage = match &upper[..upper.len() - digits.len()] {
+                "MW" => ModbusStorage::AO,
+                "IW" => ModbusStorage::AI,
+                "M" | "MX" => ModbusStorage::DO,
+                "I" | "IX" => ModbusStorage::DI,
+                _ => return Err(invalid()),
+            };
+            let start = digits.parse().map_err(|_| invalid())?;
+            return Ok(Address::Iec { storage, start });
+        }
+        if !(5..=6).contains(&value.len()) || !value.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let storage = match &value[..1] {
+            "0" => ModbusStorage::DO,
+            "1" => ModbusStorage::DI,
+            "3" => ModbusStorage::AI,
+            "4" => ModbusStorage::AO,
+            _ => return Err(invalid()),
+        };
+        let number = value[1..].parse().map_err(|_| invalid())?;
+        Ok(Address::Modicon { storage, number })
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Адрес без ведущих нулей YAML читает как число, с ведущими нулями - как строку
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Text {
+            Number(u64),
+            String(String),
+        }
+        let text = match Text::deserialize(deserializer)? {
+            Text::Number(number) => number.to_string(),
+            Text::String(text) => text,
+        };
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Address {
+    /// Область памяти и смещение от нуля. base - номер первого регистра
+    /// в нотации Modicon: 1 (40001 - смещение 0) или 0 (40000 - смещение 0)
+    pub fn resolve(&self, base: u16) -> Result<(ModbusStorage, u16), String> {
+        match *self {
+            Address::Iec { storage, start } => Ok((storage, start)),
+            Address::Modicon { storage, number } => number
+                .checked_sub(base as u32)
+                .and_then(|start| u16::try_from(start).ok())
+                .map(|start| (storage, start))
+                .ok_or_else(|| {
+                    format!(
+                        "номер регистра {number} вне диапазона {base}..{} \
+                         при нумерации регистров с {base}",
+                        0xFFFF + base as u32
+                    )
+                }),
         }
     }
 }
@@ -22,29 +319,141 @@ impl From<String> for ModbusStorage {
 #[derive(Debug, Deserialize, Clone)]
 //Структура описывает конфигурацию modbus запроса
 pub struct ConfigItem {
-    pub storage: String,
+    /// Область памяти, вместе со start заменяется адресом address
+    pub storage: Option<ModbusStorage>,
     pub id: u16,
     pub unit_id: u8,
     pub name: String,
-    pub start: u16,
+    /// Смещение первого регистра от нуля
+    pub start: Option<u16>,
+    /// Адрес в нотации Modicon или IEC вместо storage и start
+    pub address: Option<Address>,
+    /// Тип данных: bool, i16, u16, i32, u32, i64, u64, f32, f64, string, bcd16, bcd32
+    pub data_type: Option<DataType>,
+    /// Длина строки в регистрах для типа string
+    pub length: Option<u16>,
+    /// Порядок байт и слов: ABCD (по умолчанию), CDAB, BADC, DCBA
+    pub byte_order: Option<ByteOrder>,
+    /// Множитель сырого значения
+    pub scale: Option<f64>,
+    /// Смещение, прибавляемое после умножения
+    pub offset: Option<f64>,
+    /// Диапазон сырых значений для преобразования по двум точкам, например [0, 27648]
+    pub raw_range: Option<[f64; 2]>,
+    /// Диапазон инженерных значений для преобразования по двум точкам, например [0, 100]
+    pub eng_range: Option<[f64; 2]>,
+    /// Единицы измерения
+    pub unit: Option<String>,
+    /// Количество знаков после запятой при выводе
+    pub precision: Option<usize>,
+    /// Номер бита (0-15) регистра AI/AO, значение переменной - состояние бита
+    pub bit: Option<u8>,
+    /// Битовая маска регистра AI/AO, значение переменной - наличие любого бита маски
+    pub mask: Option<u16>,
+    /// Интервал опроса в секундах, по умолчанию берется из группы или канала
+    pub poll_interval: Option<f64>,
+    /// Имя группы опроса из раздела groups
+    pub group: Option<String>,
+    /// Имя канала связи, необязательно при единственном канале
+    pub channel: Option<String>,
 }
 
-impl From<ConfigItem> for ModbusRequestItems {
-    fn from(value: ConfigItem) -> Self {
-        Self {
-            storage: ModbusStorage::from(value.storage.to_owned()),
+#[derive(Debug, Deserialize, Clone)]
+/// Именованная группа переменных с общим интервалом опроса
+pub struct PollGroup {
+    pub name: String,
+    /// Интервал опроса в секундах
+    pub poll_interval: f64,
+}
+
+impl ConfigItem {
+    /// Область памяти и смещение переменной, заданные через storage и start
+    /// или адресом address. base - номер первого регистра в адресах Modicon
+    pub fn location(&self, base: u16) -> Result<(ModbusStorage, u16), String> {
+        match (self.address, self.storage, self.start) {
+            (Some(address), None, None) => address.resolve(base),
+            (Some(_), _, _) => Err("адрес address задается вместо storage и start".to_string()),
+            (None, Some(storage), Some(start)) => Ok((storage, start)),
+            (None, None, _) => {
+                Err("не указана область памяти storage или адрес address".to_string())
+            }
+            (None, Some(_), None) => Err("не указано смещение start".to_string()),
+        }
+    }
+}
+
+impl TryFrom<ConfigItem> for ModbusRequestItems {
+    type Error = ModbusError;
+
+    /// Адрес address заменяется областью памяти и смещением при разборе
+    /// конфигурации, переменная без них не преобразуется
+    fn try_from(value: ConfigItem) -> Result<Self, Self::Error> {
+        let (Some(storage), Some(start)) = (value.storage, value.start) else {
+            return Err(ModbusError::Config(format!(
+                "у переменной {} не определены область памяти и смещение",
+                value.name
+            )));
+        };
+        let bit_mask = match storage {
+            ModbusStorage::DI | ModbusStorage::DO => None,
+            ModbusStorage::AI | ModbusStorage::AO => match (value.bit, value.mask) {
+                (Some(bit), _) => 1u16.checked_shl(bit as u32),
+                (None, mask) => mask,
+            },
+        };
+        let data_type = match (storage, value.data_type) {
+            (ModbusStorage::DI | ModbusStorage::DO, _) => DataType::Bool,
+            _ if bit_mask.is_some() => DataType::Bool,
+            (_, Some(data_type)) => data_type,
+            (_, None) => DataType::U16,
+        };
+        Ok(Self {
+            storage,
             id: value.id,
             unit_id: value.unit_id,
             name: value.name,
-            start: value.start,
-        }
+            start,
+            count: match data_type {
+                DataType::String => value.length.unwrap_or(1),
+                _ => data_type.register_count(),
+            },
+            data_type,
+            byte_order: value.byte_order.unwrap_or(ByteOrder::Abcd),
+            scaling: match (value.raw_range, value.eng_range) {
+                (Some(raw_range), Some(eng_range)) => Scaling::from_ranges(raw_range, eng_range),
+                _ if value.scale.is_some() || value.offset.is_some() => Some(Scaling {
+                    scale: value.scale.unwrap_or(1.0),
+                    offset: value.offset.unwrap_or(0.0),
+                }),
+                _ => None,
+            },
+            unit: value.unit,
+            precision: value.precision,
+            poll_interval: value
+                .poll_interval
+                .and_then(|interval| Duration::try_from_secs_f64(interval).ok())
+                .filter(|interval| !interval.is_zero())
+                .unwrap_or(DEFAULT_POLL_INTERVAL),
+            bit_mask,
+        })
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct ModbusRequestItems {
     pub storage: ModbusStorage,
     pub id: u16,
     pub unit_id: u8,
     pub name: String,
     pub start: u16,
+    /// Количество бит или регистров, занимаемых переменной
+    pub count: u16,
+    pub data_type: DataType,
+    pub byte_order: ByteOrder,
+    pub scaling: Option<Scaling>,
+    pub unit: Option<String>,
+    pub precision: Option<usize>,
+    /// Маска битов регистра для логических переменных в AI/AO
+    pub bit_mask: Option<u16>,
+    pub poll_interval: Duration,
 }
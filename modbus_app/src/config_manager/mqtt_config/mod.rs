@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use rumqttc::{LastWill, MqttOptions, QoS};
+use serde::Deserialize;
+
+use crate::error::ModbusError;
+
+/// Сообщение о состоянии канала, когда связь с устройствами есть
+pub const STATUS_ONLINE: &str = "online";
+/// Сообщение о состоянии канала при потере связи, в том числе завещание клиента
+pub const STATUS_OFFLINE: &str = "offline";
+
+#[derive(Debug, Deserialize, Clone, Default)]
+/// Структура описывает публикацию значений переменных в брокер MQTT
+pub struct MqttConfig {
+    /// Адрес брокера, по умолчанию localhost
+    pub host: Option<String>,
+    /// Порт брокера, по умолчанию 1883
+    pub port: Option<u16>,
+    /// Идентификатор клиента, к нему добавляется имя канала; по умолчанию modbus_app
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Шаблон топика переменной с подстановками {channel} и {name},
+    /// по умолчанию modbus/{channel}/{name}. Запись принимается из топика с суффиксом /set
+    pub topic: Option<String>,
+    /// Шаблон топика состояния канала, по умолчанию modbus/{channel}/status
+    pub status_topic: Option<String>,
+    /// Публиковать значения с флагом retain, по умолчанию false
+    pub retain: Option<bool>,
+    /// Уровень качества доставки: 0, 1, 2, по умолчанию 0
+    pub qos: Option<u8>,
+    /// Интервал keep alive в секундах, по умолчанию 30
+    pub keep_alive: Option<f64>,
+}
+
+impl MqttConfig {
+    /// Топик значения переменной канала
+    pub fn topic(&self, channel: &str, name: &str) -> String {
+        self.topic
+            .as_deref()
+            .unwrap_or("modbus/{channel}/{name}")
+            .replace("{channel}", channel)
+            .replace("{name}", name)
+    }
+
+    /// Топик состояния канала
+    pub fn status_topic(&self, channel: &str) -> String {
+        self.status_topic
+            .as_deref()
+            .unwrap_or("modbus/{channel}/status")
+            .replace("{channel}", channel)
+    }
+
+    pub fn retain(&self) -> bool {
+        self.retain.unwrap_or(false)
+    }
+
+    pub fn qos(&self) -> Result<QoS, ModbusError> {
+        let qos = self.qos.unwrap_or(0);
+        rumqttc::qos(qos)
+            .map_err(|_| ModbusError::Config(format!("недопустимый уровень QoS {qos}")))
+    }
+
+    /// Параметры подключения клиента канала. Каждый канал подключается
+    /// отдельным клиентом, чтобы брокер опубликовал завещание только его канала
+    pub fn options(&self, channel: &str) -> Result<MqttOptions, ModbusError> {
+        let client_id = format!(
+            "{}-{channel}",
+            self.client_id.as_deref().unwrap_or("modbus_app")
+        );
+        let host = self.host.as_deref().unwrap_or("localhost");
+        let mut options = MqttOptions::new(client_id, host, self.port.unwrap_or(1883));
+        let keep_alive = self.keep_alive.unwrap_or(30.0);
+        if !(keep_alive.is_finite() && keep_alive >= 1.0) {
+            return Err(ModbusError::Config(
+                "интервал keep alive MQTT должен быть не меньше секунды".to_string(),
+            ));
+        }
+        options
+            .set_keep_alive(Duration::from_secs_f64(keep_alive))
+            .set_last_will(LastWill::new(
+                self.status_topic(channel),
+                STATUS_OFFLINE,
+                self.qos()?,
+                true,
+            ));
+        if let Some(username) = &self.username {
+            options.set_credentials(username, self.password.as_deref().unwrap_or_default());
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topics_are_rendered() -> Result<(), Box<dyn std::error::Error>> {
+        let config: MqttConfig = serde_yaml::from_str("{topic: 'plant/{channel}/{name}', qos: 1}")?;
+        assert_eq!(
+            config.topic("boiler", "temperature"),
+            "plant/boiler/temperature"
+        );
+        assert_eq!(config.status_topic("boiler"), "modbus/boiler/status");
+        assert_eq!(config.qos()?, QoS::AtLeastOnce);
+        let options = config.options("boiler")?;
+        assert_eq!(options.client_id(), "modbus_app-boiler");
+        assert_eq!(
+            options.last_will().map(|will| will.topic),
+            Some("modbus/boiler/status".to_string())
+        );
+        let invalid = MqttConfig {
+            qos: Some(3),
+            ..Default::default()
+        };
+        assert!(invalid.options("boiler").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,29 @@
+use serde::Deserialize;
+use std::path::PathBuf;
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+/// Структура описывает начальное состояние памяти симулятора
+pub struct SimulatorConfig {
+    /// Адрес симулируемого устройства, по умолчанию 1
+    pub unit_id: Option<u8>,
+    pub coils: Vec<SimulatorBlock<bool>>,
+    pub discrete_inputs: Vec<SimulatorBlock<bool>>,
+    pub holding_registers: Vec<SimulatorBlock<u16>>,
+    pub input_registers: Vec<SimulatorBlock<u16>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+/// Значения, записываемые подряд начиная с адреса start
+pub struct SimulatorBlock<T> {
+    pub start: u16,
+    pub values: Vec<T>,
+}
+
+impl SimulatorConfig {
+    pub fn try_read_config_file(path: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
+        let open_file = std::fs::File::open(path)?;
+        let config: Self = serde_yaml::from_reader(open_file)?;
+        Ok(config)
+    }
+}
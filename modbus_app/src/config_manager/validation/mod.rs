@@ -0,0 +1,721 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::Duration;
+
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+
+use super::channel_config::ChannelConfig;
+use super::modbus_variables::{ConfigItem, DataType, ModbusRequestItems, ModbusStorage, Scaling};
+use super::Config;
+use crate::task::ProtocolType;
+
+/// Элемент пути к узлу YAML: поле отображения или индекс списка
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    Field(&'static str),
+    Index(usize),
+}
+
+/// Ошибка конфигурации с местом в файле
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Путь к узлу, например variables[3].storage
+    pub path: String,
+    /// Строка и столбец узла, начиная с 1
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((line, column)) = self.location {
+            write!(f, "строка {line}, столбец {column}: ")?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<serde_yaml::Error> for Diagnostic {
+    fn from(value: serde_yaml::Error) -> Self {
+        let location = value
+            .location()
+            .map(|location| (location.line(), location.column()));
+        let message = value.to_string();
+        // Положение выводится отдельно, поэтому убирается из текста ошибки
+        let message = match location {
+            Some((line, column)) => message
+                .trim_end_matches(&format!(" at line {line} column {column}"))
+                .to_string(),
+            None => message,
+        };
+        Self {
+            path: String::new(),
+            location,
+            message,
+        }
+    }
+}
+
+/// Все ошибки, найденные в конфигурации
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+/// Ошибка, возвращенная из main, выводится через Debug
+impl std::fmt::Debug for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ошибки конфигурации:")?;
+        for diagnostic in &self.0 {
+            write!(f, "\n  {diagnostic}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// Проверяет согласованность конфигурации, прочитанной из source
+pub fn validate(config: &Config, source: &str) -> Vec<Diagnostic> {
+    let mut checks = Checks::default();
+    checks.channels(config);
+    checks.groups(config);
+    checks.variables(config);
+    checks
+        .found
+        .into_iter()
+        .map(|(path, message)| Diagnostic {
+            location: locate(source, &path),
+            path: path_to_string(&path),
+            message,
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Checks {
+    found: Vec<(Vec<Key>, String)>,
+}
+
+impl Checks {
+    fn report(&mut self, path: Vec<Key>, message: String) {
+        self.found.push((path, message));
+    }
+
+    fn channels(&mut self, config: &Config) {
+        let mut names = HashMap::new();
+        for (path, channel) in channels_with_paths(config) {
+            let field = |name| [path.as_slice(), &[Key::Field(name)]].concat();
+            match channel.protocol_type() {
+                ProtocolType::Tcp => {
+                    if channel.port == Some(0) {
+                        self.report(field("port"), "недопустимый порт 0".to_string());
+                    }
+                }
+                ProtocolType::Uart => {
+                    if channel.baud_rate == Some(0) {
+                        self.report(field("baud_rate"), "недопустимая скорость 0".to_string());
+                    }
+                }
+            }
+            if let Some(timeout) = channel
+                .timeout
+                .filter(|timeout| *timeout <= 0.0 || Duration::try_from_secs_f64(*timeout).is_err())
+            {
+                self.report(
+                    field("timeout"),
+                    format!("недопустимое время ожидания {timeout}, ожидается положительное число секунд"),
+                );
+            }
+            self.poll_interval(field("poll_interval"), channel.poll_interval);
+            if let Some(base) = channel.address_base.filter(|base| *base > 1) {
+                self.report(
+                    field("address_base"),
+                    format!("номер первого регистра {base}, ожидается 0 или 1"),
+                );
+            }
+            // Имя канала входит в топики и идентификатор клиента MQTT, а адрес
+            // канала содержит символы / и :
+            #[cfg(feature = "mqtt")]
+            if config.mqtt.is_some() {
+                match &channel.name {
+                    None => self.report(
+                        path.to_owned(),
+                        "не указано имя name, обязательное при публикации в MQTT".to_string(),
+                    ),
+                    Some(name) if name.contains(['/', '+', '#']) => self.report(
+                        field("name"),
+                        format!("имя {name} содержит недопустимые в топиках MQTT символы / + #"),
+                    ),
+                    Some(_) => {}
+                }
+            }
+            let name = channel.name();
+            if let Some(first) = names.insert(name.to_owned(), path_to_string(&path)) {
+                self.report(field("name"), format!("канал {name} уже описан в {first}"));
+            }
+        }
+    }
+
+    fn groups(&mut self, config: &Config) {
+        for (index, group) in config.groups.iter().enumerate() {
+            self.poll_interval(
+                vec![
+                    Key::Field("groups"),
+                    Key::Index(index),
+                    Key::Field("poll_interval"),
+                ],
+                Some(group.poll_interval),
+            );
+        }
+    }
+
+    /// Интервал опроса должен быть положительным числом секунд
+    fn poll_interval(&mut self, path: Vec<Key>, interval: Option<f64>) {
+        if let Some(interval) = interval
+            .filter(|interval| *interval <= 0.0 || Duration::try_from_secs_f64(*interval).is_err())
+        {
+            self.report(
+                path,
+                format!(
+                    "недопустимый интервал опроса {interval}, ожидается положительное число секунд"
+                ),
+            );
+        }
+    }
+
+    fn variables(&mut self, config: &Config) {
+        let configs = channels_with_paths(config)
+            .into_iter()
+            .map(|(_, channel)| channel)
+            .collect::<Vec<_>>();
+        let channels = configs
+            .iter()
+            .map(|channel| channel.name())
+            .collect::<Vec<_>>();
+        let mut names = HashMap::new();
+        // Без указания канала переменная относится к единственному каналу
+        let default_channel = match channels.as_slice() {
+            [channel] => Some(channel.to_owned()),
+            _ => None,
+        };
+        let mut ranges: Vec<(usize, &String, Option<String>, ModbusRequestItems)> = vec![];
+        for (index, item) in config.variables.iter().enumerate() {
+            let field = |name| vec![Key::Field("variables"), Key::Index(index), Key::Field(name)];
+            if let Some(first) = names.insert(&item.name, index) {
+                self.report(
+                    field("name"),
+                    format!("имя {} уже использовано в variables[{first}]", item.name),
+                );
+            }
+            if item.unit_id == 0 || item.unit_id >= 248 {
+                self.report(
+                    field("unit_id"),
+                    format!("адрес устройства {} вне диапазона 1..247", item.unit_id),
+                );
+            }
+            if item.channel.is_none() && default_channel.is_none() {
+                let message = match channels.is_empty() {
+                    true => "не описан ни один канал",
+                    false => "не указан канал channel, описано несколько каналов",
+                };
+                self.report(
+                    vec![Key::Field("variables"), Key::Index(index)],
+                    message.to_string(),
+                );
+            }
+            self.poll_interval(field("poll_interval"), item.poll_interval);
+            let channel = item.channel.to_owned().or(default_channel.to_owned());
+            let base = configs
+                .iter()
+                .find(|config| Some(config.name()) == channel)
+                .map(|config| config.address_base())
+                .unwrap_or(1);
+            // Ошибки адреса относятся к полю address, если он задан
+            let start_field = match item.address {
+                Some(_) => "address",
+                None => "start",
+            };
+            let (storage, start) = match item.location(base) {
+                Ok(location) => location,
+                Err(message) => {
+                    let path = match item.address {
+                        Some(_) => field("address"),
+                        None => vec![Key::Field("variables"), Key::Index(index)],
+                    };
+                    self.report(path, message);
+                    continue;
+                }
+            };
+            if let Some(bit) = item.bit.filter(|bit| *bit > 15) {
+                self.report(
+                    field("bit"),
+                    format!("номер бита {bit} вне диапазона 0..15"),
+                );
+            }
+            // Битовые области и битовые переменные всегда имеют тип bool,
+            // поэтому эти поля иначе молча игнорировались бы
+            if matches!(storage, ModbusStorage::DI | ModbusStorage::DO) {
+                let fields = [
+                    (
+                        "data_type",
+                        item.data_type
+                            .is_some_and(|data_type| data_type != DataType::Bool),
+                    ),
+                    ("bit", item.bit.is_some()),
+                    ("mask", item.mask.is_some()),
+                ];
+                for (name, _) in fields.into_iter().filter(|(_, set)| *set) {
+                    self.report(
+                        field(name),
+                        format!("поле {name} не применяется к дискретной области {storage:?}"),
+                    );
+                }
+            } else if item.bit.is_some() && item.mask.is_some() {
+                self.report(
+                    field("mask"),
+                    "маска mask задается вместо номера бита bit".to_string(),
+                );
+            }
+            if item.mask == Some(0) {
+                self.report(
+                    field("mask"),
+                    "маска 0 не выделяет ни одного бита".to_string(),
+                );
+            }
+            // Нулевой множитель дает постоянное значение, и записать
+            // переменную нельзя, так как обратное преобразование делит на него
+            if item.scale == Some(0.0) {
+                self.report(field("scale"), "множитель scale равен 0".to_string());
+            }
+            // Без масштабирования переменная публиковала бы сырые значения
+            match (item.raw_range, item.eng_range) {
+                (Some(raw_range), Some(eng_range)) => {
+                    if Scaling::from_ranges(raw_range, eng_range).is_none() {
+                        self.report(
+                            field("raw_range"),
+                            format!(
+                                "диапазон raw_range {}..{} нулевой ширины",
+                                raw_range[0], raw_range[1]
+                            ),
+                        );
+                    }
+                    if eng_range[0] == eng_range[1] {
+                        self.report(
+                            field("eng_range"),
+                            format!(
+                                "диапазон eng_range {}..{} нулевой ширины",
+                                eng_range[0], eng_range[1]
+                            ),
+                        );
+                    }
+                }
+                (Some(_), None) => self.report(
+                    field("raw_range"),
+                    "не указан диапазон eng_range".to_string(),
+                ),
+                (None, Some(_)) => self.report(
+                    field("eng_range"),
+                    "не указан диапазон raw_range".to_string(),
+                ),
+                (None, None) => {}
+            }
+            let request = match ModbusRequestItems::try_from(ConfigItem {
+                storage: Some(storage),
+                start: Some(start),
+                address: None,
+                ..item.to_owned()
+            }) {
+                Ok(request) => request,
+                Err(err) => {
+                    self.report(
+                        vec![Key::Field("variables"), Key::Index(index)],
+                        err.to_string(),
+                    );
+                    continue;
+                }
+            };
+            if request.count == 0 || request.count > storage.max_count() {
+                let path = match request.data_type {
+                    DataType::String => field("length"),
+                    _ => field("data_type"),
+                };
+                self.report(
+                    path,
+                    format!(
+                        "количество {} вне допустимого для одного запроса диапазона 1..{}",
+                        request.count,
+                        storage.max_count()
+                    ),
+                );
+                continue;
+            }
+            // Адреса переменной start..=end, считаются в u32, чтобы не переполниться
+            let end = request.start as u32 + request.count as u32 - 1;
+            if end > u16::MAX as u32 {
+                self.report(
+                    field(start_field),
+                    format!(
+                        "переменная выходит за адрес 65535: начало {}, количество {}",
+                        request.start, request.count
+                    ),
+                );
+                continue;
+            }
+            if let Some(channel) = item
+                .channel
+                .as_ref()
+                .filter(|name| !channels.contains(name))
+            {
+                self.report(field("channel"), format!("канал {channel} не описан"));
+            }
+            if let Some(group) = item
+                .group
+                .as_ref()
+                .filter(|name| !config.groups.iter().any(|group| &&group.name == name))
+            {
+                self.report(field("group"), format!("группа {group} не описана"));
+            }
+            if let Some((other, other_name, _, _)) =
+                ranges.iter().find(|(_, _, other_channel, other)| {
+                    other.storage == request.storage
+                        && other.unit_id == request.unit_id
+                        && other_channel == &channel
+                        // Битовые переменные одного регистра не пересекаются
+                        && !(other.bit_mask.is_some() && request.bit_mask.is_some())
+                        && other.start as u32 <= end
+                        && (request.start as u32) < other.start as u32 + other.count as u32
+                })
+            {
+                self.report(
+                    field(start_field),
+                    format!(
+                        "адреса {}..{} пересекаются с переменной {other_name} (variables[{other}])",
+                        request.start, end,
+                    ),
+                );
+            }
+            ranges.push((index, &item.name, channel, request));
+        }
+    }
+}
+
+/// Каналы конфигурации вместе с путями к ним
+fn channels_with_paths(config: &Config) -> Vec<(Vec<Key>, &ChannelConfig)> {
+    let legacy = config
+        .channel
+        .iter()
+        .map(|channel| (vec![Key::Field("channel")], channel));
+    let channels = config
+        .channels
+        .iter()
+        .enumerate()
+        .map(|(index, channel)| (vec![Key::Field("channels"), Key::Index(index)], channel));
+    legacy.chain(channels).collect()
+}
+
+fn path_to_string(path: &[Key]) -> String {
+    path.iter().fold(String::new(), |mut result, key| {
+        match key {
+            Key::Field(name) if result.is_empty() => result.push_str(name),
+            Key::Field(name) => {
+                result.push('.');
+                result.push_str(name);
+            }
+            Key::Index(index) => result.push_str(&format!("[{index}]")),
+        }
+        result
+    })
+}
+
+/// Сообщение, которым проба останавливает разбор на искомом узле
+const FOUND: &str = "узел найден";
+
+/// Строка и столбец узла YAML по пути.
+///
+/// serde_yaml не сообщает положение разобранных узлов, но указывает его в
+/// ошибках. Поэтому документ разбирается заново пробой, которая возвращает
+/// ошибку на искомом узле, и положение берется из этой ошибки.
+pub fn locate(source: &str, path: &[Key]) -> Option<(usize, usize)> {
+    let err = Probe(path)
+        .deserialize(serde_yaml::Deserializer::from_str(source))
+        .err()?;
+    if !err.to_string().contains(FOUND) {
+        return None;
+    }
+    err.location()
+        .map(|location| (location.line(), location.column()))
+}
+
+/// Проба, проходящая документ по пути до искомого узла
+struct Probe<'a>(&'a [Key]);
+
+impl Probe<'_> {
+    fn found<E: de::Error>(&self) -> Result<(), E> {
+        match self.0 {
+            [] => Err(E::custom(FOUND)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Probe<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Probe<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "любой узел YAML")
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<(), E> {
+        self.found()
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<(), E> {
+        self.found()
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<(), E> {
+        self.found()
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<(), E> {
+        self.found()
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> Result<(), E> {
+        self.found()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        self.found()
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        self.found()?;
+        if let [Key::Index(index), rest @ ..] = self.0 {
+            for _ in 0..*index {
+                if seq.next_element::<IgnoredAny>()?.is_none() {
+                    return Ok(());
+                }
+            }
+            seq.next_element_seed(Probe(rest))?;
+        }
+        // Непрочитанные элементы serde_yaml считает ошибкой длины
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        self.found()?;
+        while let Some(key) = map.next_key::<serde_yaml::Value>()? {
+            match self.0 {
+                [Key::Field(name), rest @ ..] if key.as_str() == Some(name) => {
+                    map.next_value_seed(Probe(rest))?
+                }
+                _ => map.next_value::<IgnoredAny>().map(|_| ())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+channels:
+  - {name: boiler, host: 10.0.0.5, timeout: -1}
+  - name: pumps
+    protocol: Uart
+    baud_rate: 0
+variables:
+  - {storage: ai, id: 1, unit_id: 1, name: temperature, start: 0, data_type: f32, channel: boiler}
+  - {storage: holding, id: 2, unit_id: 1, name: pressure, start: 10, channel: boiler}
+  - {storage: ai, id: 3, unit_id: 0, name: temperature, start: 1, channel: boiler}
+  - {storage: ai, id: 5, unit_id: 1, name: level, start: 1, channel: boiler}
+  - storage: ao
+    id: 4
+    unit_id: 3
+    name: label
+    start: 0
+    data_type: string
+    length: 200
+    channel: pumps
+";
+
+    #[test]
+    fn locate_finds_nodes() {
+        let path = [Key::Field("variables"), Key::Index(4), Key::Field("length")];
+        assert_eq!(locate(CONFIG, &path), Some((18, 13)));
+        let path = [Key::Field("variables"), Key::Index(1)];
+        assert_eq!(locate(CONFIG, &path), Some((9, 5)));
+        assert_eq!(locate(CONFIG, &[Key::Field("missing")]), None);
+        assert_eq!(path_to_string(&path), "variables[1]");
+    }
+
+    #[test]
+    fn validate_reports_problems_with_locations() -> Result<(), Box<dyn std::error::Error>> {
+        let config: Config = serde_yaml::from_str(CONFIG)?;
+        let diagnostics = validate(&config, CONFIG)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.path, diagnostic.location))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            vec![
+                ("channels[0].timeout".to_string(), Some((3, 45))),
+                ("channels[1].baud_rate".to_string(), Some((6, 16))),
+                ("variables[2].name".to_string(), Some((10, 44))),
+                ("variables[2].unit_id".to_string(), Some((10, 35))),
+                ("variables[3].start".to_string(), Some((11, 58))),
+                ("variables[4].length".to_string(), Some((18, 13))),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn top_of_address_space_does_not_overflow() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "
+channel: {host: 10.0.0.5}
+variables:
+  - {storage: ai, id: 1, unit_id: 1, name: low, start: 10}
+  - {storage: ai, id: 2, unit_id: 1, name: last, start: 65535}
+  - {storage: ai, id: 3, unit_id: 1, name: also_last, start: 65535}
+  - {storage: ai, id: 4, unit_id: 1, name: past_end, start: 65535, data_type: u32}
+";
+        let config: Config = serde_yaml::from_str(source)?;
+        let diagnostics = validate(&config, source)
+            .into_iter()
+            .map(|diagnostic| diagnostic.path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            vec![
+                "variables[2].start".to_string(),
+                "variables[3].start".to_string()
+            ]
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "mqtt")]
+    #[test]
+    fn mqtt_requires_channel_names() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "
+mqtt: {host: broker}
+channels:
+  - {host: 10.0.0.5}
+  - {name: pumps/east, host: 10.0.0.6}
+  - {name: boiler, host: 10.0.0.7}
+variables: []
+";
+        let config: Config = serde_yaml::from_str(source)?;
+        let diagnostics = validate(&config, source)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.path, diagnostic.location))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            vec![
+                ("channels[0]".to_string(), Some((4, 5))),
+                ("channels[1].name".to_string(), Some((5, 12))),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn constant_values_are_reported() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "
+channel: {host: 10.0.0.5}
+variables:
+  - {storage: ai, id: 1, unit_id: 1, name: none, start: 0, mask: 0}
+  - {storage: ai, id: 2, unit_id: 1, name: zero, start: 1, scale: 0}
+  - {storage: ai, id: 3, unit_id: 1, name: flat, start: 2, raw_range: [0, 10], eng_range: [5, 5]}
+  - {storage: ai, id: 4, unit_id: 1, name: alarm, start: 3, mask: 0x0F}
+";
+        let config: Config = serde_yaml::from_str(source)?;
+        let diagnostics = validate(&config, source)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.path, diagnostic.location))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            vec![
+                ("variables[0].mask".to_string(), Some((4, 66))),
+                ("variables[1].scale".to_string(), Some((5, 67))),
+                ("variables[2].eng_range".to_string(), Some((6, 91))),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ignored_bit_fields_are_reported() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "
+channel: {host: 10.0.0.5}
+variables:
+  - {storage: do, id: 1, unit_id: 1, name: pump, start: 0, data_type: u16, bit: 3}
+  - {storage: di, id: 2, unit_id: 1, name: alarm, start: 0, mask: 0x0F, data_type: bool}
+  - {storage: ao, id: 3, unit_id: 1, name: mode, start: 0, bit: 1, mask: 0x06}
+  - {storage: ao, id: 4, unit_id: 1, name: ready, start: 1, bit: 1}
+";
+        let config: Config = serde_yaml::from_str(source)?;
+        let diagnostics = validate(&config, source)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.path, diagnostic.location))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            vec![
+                ("variables[0].data_type".to_string(), Some((4, 71))),
+                ("variables[0].bit".to_string(), Some((4, 81))),
+                ("variables[1].mask".to_string(), Some((5, 67))),
+                ("variables[2].mask".to_string(), Some((6, 74))),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn incomplete_scaling_is_reported() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "
+channel: {host: 10.0.0.5}
+variables:
+  - {storage: ai, id: 1, unit_id: 1, name: flat, start: 0, raw_range: [5, 5], eng_range: [0, 100]}
+  - {storage: ai, id: 2, unit_id: 1, name: raw_only, start: 1, raw_range: [0, 4095]}
+  - {storage: ai, id: 3, unit_id: 1, name: eng_only, start: 2, eng_range: [0, 10]}
+  - {storage: ai, id: 4, unit_id: 1, name: level, start: 3, raw_range: [0, 4095], eng_range: [0, 10]}
+";
+        let config: Config = serde_yaml::from_str(source)?;
+        let diagnostics = validate(&config, source)
+            .into_iter()
+            .map(|diagnostic| diagnostic.path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            vec![
+                "variables[0].raw_range".to_string(),
+                "variables[1].raw_range".to_string(),
+                "variables[2].eng_range".to_string(),
+            ]
+        );
+        Ok(())
+    }
+}
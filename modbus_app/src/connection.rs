@@ -0,0 +1,337 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt::Display,
+    hash::{BuildHasher, Hasher},
+    sync::mpsc::Sender,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "async")]
+use crate::config_manager::channel_config::AsyncConnect;
+use crate::config_manager::channel_config::Connect;
+
+/// Состояние соединения с каналом связи
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected,
+    /// Ожидание перед следующей попыткой подключения
+    Backoff,
+}
+
+impl Display for ConnectionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionState::Disconnected => write!(f, "соединение разорвано"),
+            ConnectionState::Connecting => write!(f, "подключение"),
+            ConnectionState::Connected => write!(f, "соединение установлено"),
+            ConnectionState::Backoff => write!(f, "ожидание переподключения"),
+        }
+    }
+}
+
+/// Событие смены состояния соединения
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateChange {
+    /// Имя канала связи
+    pub channel: String,
+    pub from: ConnectionState,
+    pub to: ConnectionState,
+    /// Причина перехода: ошибка связи или время ожидания
+    pub reason: Option<String>,
+}
+
+impl Display for StateChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Канал {}: {}", self.channel, self.to)?;
+        match &self.reason {
+            Some(reason) => write!(f, " ({reason})"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Экспоненциально растущая пауза между попытками подключения
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    /// Доля паузы (0..1), на которую она случайно сокращается,
+    /// чтобы каналы не переподключались одновременно
+    jitter: f64,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration, jitter: f64) -> Self {
+        Self {
+            min,
+            max: max.max(min),
+            jitter: jitter.clamp(0.0, 1.0),
+            attempt: 0,
+        }
+    }
+
+    /// Пауза перед очередной попыткой: min, 2*min, 4*min... но не более max
+    pub fn next_delay(&mut self) -> Duration {
+        let base = self
+            .min
+            .checked_mul(2u32.saturating_pow(self.attempt))
+            .unwrap_or(self.max)
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+        let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        base.mul_f64(1.0 - self.jitter * random)
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// Соединение с каналом связи, восстанавливаемое после ошибок
+pub struct Connection<C, S> {
+    name: String,
+    connector: C,
+    backoff: Backoff,
+    state: ConnectionState,
+    stream: Option<S>,
+    retry_at: Instant,
+    subscribers: Vec<Sender<StateChange>>,
+}
+
+impl<C, S> Connection<C, S> {
+    pub fn new(name: String, connector: C, backoff: Backoff) -> Self {
+        Self {
+            name,
+            connector,
+            backoff,
+            state: ConnectionState::Disconnected,
+            stream: None,
+            retry_at: Instant::now(),
+            subscribers: vec![],
+        }
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Пауза перед следующей попыткой подключения ещё не истекла
+    pub fn is_waiting(&self) -> bool {
+        !self.retry_delay().is_zero()
+    }
+
+    /// Подписывает получателя на события смены состояния
+    pub fn subscribe(&mut self, sender: Sender<StateChange>) {
+        self.subscribers.push(sender);
+    }
+
+    /// Закрывает соединение после ошибки канала связи, в том числе после
+    /// отсутствия ответа на полуоткрытом TCP соединении, и переходит в
+    /// Backoff. Повторное подключение выполняется после паузы, иначе
+    /// устройство, принимающее подключения, но не отвечающее на запросы,
+    /// опрашивалось бы без пауз
+    pub fn disconnect(&mut self, reason: String) {
+        self.stream = None;
+        self.set_state(ConnectionState::Disconnected, Some(reason));
+        self.schedule_retry();
+    }
+
+    /// Назначает время следующей попытки подключения и переходит в Backoff
+    fn schedule_retry(&mut self) {
+        let delay = self.backoff.next_delay();
+        self.retry_at = Instant::now() + delay;
+        self.set_state(
+            ConnectionState::Backoff,
+            Some(format!("повтор через {delay:.1?}")),
+        );
+    }
+
+    /// Обмен по соединению прошел успешно: пауза перед переподключением
+    /// снова начинается с наименьшей
+    pub fn confirm(&mut self) {
+        self.backoff.reset();
+    }
+
+    /// Оставшаяся пауза перед попыткой подключения
+    fn retry_delay(&self) -> Duration {
+        match self.state {
+            ConnectionState::Backoff => self.retry_at.saturating_duration_since(Instant::now()),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Переводит соединение в Connected или, при ошибке, в Backoff
+    fn connected(&mut self, result: Result<S, String>) {
+        match result {
+            Ok(stream) => {
+                self.stream = Some(stream);
+                self.set_state(ConnectionState::Connected, None);
+            }
+            Err(err) => {
+                self.set_state(ConnectionState::Disconnected, Some(err));
+                self.schedule_retry();
+            }
+        }
+    }
+
+    fn set_state(&mut self, state: ConnectionState, reason: Option<String>) {
+        if self.state == state {
+            return;
+        }
+        let event = StateChange {
+            channel: self.name.to_owned(),
+            from: self.state,
+            to: state,
+            reason,
+        };
+        self.state = state;
+        // Отписавшиеся получатели больше не получают события
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.to_owned()).is_ok());
+    }
+}
+
+impl<C, S> Connection<C, S>
+where
+    C: Connect<Output = Result<S, Box<dyn std::error::Error>>>,
+{
+    /// Возвращает канал связи, при необходимости подключаясь к нему.
+    ///
+    /// В состоянии Backoff сначала выдерживается пауза. При неудачной попытке
+    /// подключения соединение переходит в Backoff и возвращается None.
+    pub fn stream(&mut self) -> Option<&mut S> {
+        if self.state != ConnectionState::Connected {
+            std::thread::sleep(self.retry_delay());
+            self.set_state(ConnectionState::Connecting, None);
+            let result = self.connector.connect().map_err(|err| err.to_string());
+            self.connected(result);
+        }
+        self.stream.as_mut()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<C, S> Connection<C, S>
+where
+    C: AsyncConnect<Output = Result<S, Box<dyn std::error::Error + Send + Sync>>>,
+{
+    /// Асинхронный вариант stream, пауза выдерживается без блокировки потока
+    pub async fn stream_async(&mut self) -> Option<&mut S> {
+        if self.state != ConnectionState::Connected {
+            tokio::time::sleep(self.retry_delay()).await;
+            self.set_state(ConnectionState::Connecting, None);
+            let result = self
+                .connector
+                .connect_async()
+                .await
+                .map_err(|err| err.to_string());
+            self.connected(result);
+        }
+        self.stream.as_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, sync::mpsc::channel};
+
+    /// Подключение, успешное только начиная с заданной попытки
+    struct FlakyConnector {
+        attempts: Cell<u32>,
+        succeed_at: u32,
+    }
+
+    impl Connect for FlakyConnector {
+        type Output = Result<u32, Box<dyn std::error::Error>>;
+        fn connect(&self) -> Self::Output {
+            let attempt = self.attempts.get() + 1;
+            self.attempts.set(attempt);
+            if attempt < self.succeed_at {
+                Err("connection refused")?;
+            }
+            Ok(attempt)
+        }
+    }
+
+    #[test]
+    fn backoff_grows_to_max() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(500), 0.0);
+        let delays = (0..5).map(|_| backoff.next_delay()).collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            [100, 200, 400, 500, 500]
+                .map(Duration::from_millis)
+                .to_vec()
+        );
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn backoff_jitter_shortens_delay() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(1), 0.5);
+        for _ in 0..20 {
+            let delay = backoff.next_delay();
+            assert!(delay > Duration::from_millis(499) && delay <= Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn connection_reports_state_changes() {
+        let connector = FlakyConnector {
+            attempts: Cell::new(0),
+            succeed_at: 2,
+        };
+        let backoff = Backoff::new(Duration::from_millis(1), Duration::from_millis(1), 0.0);
+        let mut connection = Connection::new("plc".to_string(), connector, backoff);
+        let (sender, receiver) = channel();
+        connection.subscribe(sender);
+        assert_eq!(connection.stream(), None);
+        assert_eq!(connection.stream(), Some(&mut 2));
+        connection.disconnect("нет ответа".to_string());
+        assert_eq!(connection.stream(), Some(&mut 3));
+        let states = receiver
+            .try_iter()
+            .map(|event| event.to)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            states,
+            vec![
+                ConnectionState::Connecting,
+                ConnectionState::Disconnected,
+                ConnectionState::Backoff,
+                ConnectionState::Connecting,
+                ConnectionState::Connected,
+                ConnectionState::Disconnected,
+                ConnectionState::Backoff,
+                ConnectionState::Connecting,
+                ConnectionState::Connected,
+            ]
+        );
+    }
+
+    #[test]
+    fn backoff_resets_only_after_successful_exchange() {
+        let connector = FlakyConnector {
+            attempts: Cell::new(0),
+            succeed_at: 1,
+        };
+        let backoff = Backoff::new(Duration::from_millis(20), Duration::from_secs(1), 0.0);
+        let mut connection = Connection::new("plc".to_string(), connector, backoff);
+        // Подключение без ответов на запросы не сбрасывает паузу
+        assert_eq!(connection.stream(), Some(&mut 1));
+        connection.disconnect("нет ответа".to_string());
+        assert!(connection.retry_delay() > Duration::from_millis(10));
+        assert_eq!(connection.stream(), Some(&mut 2));
+        connection.disconnect("нет ответа".to_string());
+        assert!(connection.retry_delay() > Duration::from_millis(30));
+        assert_eq!(connection.stream(), Some(&mut 3));
+        connection.confirm();
+        connection.disconnect("нет ответа".to_string());
+        assert!(connection.retry_delay() <= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,500 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::config_manager::modbus_variables::{ByteOrder, DataType, ModbusRequestItems, Scaling};
+
+/// Значение переменной после декодирования регистров
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    String(String),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::I16(value) => write!(f, "{value}"),
+            Value::U16(value) => write!(f, "{value}"),
+            Value::I32(value) => write!(f, "{value}"),
+            Value::U32(value) => write!(f, "{value}"),
+            Value::I64(value) => write!(f, "{value}"),
+            Value::U64(value) => write!(f, "{value}"),
+            Value::F32(value) => write!(f, "{value}"),
+            Value::F64(value) => write!(f, "{value}"),
+            Value::String(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl Value {
+    /// Числовое представление значения, None для строк
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Bool(value) => Some(if *value { 1.0 } else { 0.0 }),
+            Value::I16(value) => Some(*value as f64),
+            Value::U16(value) => Some(*value as f64),
+            Value::I32(value) => Some(*value as f64),
+            Value::U32(value) => Some(*value as f64),
+            Value::I64(value) => Some(*value as f64),
+            Value::U64(value) => Some(*value as f64),
+            Value::F32(value) => Some(*value as f64),
+            Value::F64(value) => Some(*value),
+            Value::String(_) => None,
+        }
+    }
+
+    /// Переводит сырое значение в инженерные единицы.
+    /// Логические значения и строки не масштабируются.
+    pub fn scale(self, scaling: &Scaling) -> Value {
+        match self {
+            Value::Bool(_) | Value::String(_) => self,
+            _ => match self.as_f64() {
+                Some(raw) => Value::F64(scaling.apply(raw)),
+                None => self,
+            },
+        }
+    }
+
+    /// Представление значения для вывода с заданной точностью и единицами измерения
+    pub fn format(&self, precision: Option<usize>, unit: Option<&str>) -> String {
+        let value = match (self, precision) {
+            (Value::F32(_) | Value::F64(_), Some(precision)) => {
+                format!("{:.*}", precision, self.as_f64().unwrap_or_default())
+            }
+            _ => self.to_string(),
+        };
+        match unit {
+            Some(unit) => format!("{value} {unit}"),
+            None => value,
+        }
+    }
+}
+
+/// Собирает регистры в одно число, первый регистр - старшее слово
+fn combine(registers: &[u16]) -> u64 {
+    registers
+        .iter()
+        .fold(0u64, |acc, &register| (acc << 16) | register as u64)
+}
+
+/// Переводит двоично-десятичное число в двоичное, None при недопустимой цифре
+fn from_bcd(raw: u64, digits: u32) -> Option<u64> {
+    let mut result = 0u64;
+    for position in (0..digits).rev() {
+        let digit = (raw >> (position * 4)) & 0x0F;
+        if digit > 9 {
+            return None;
+        }
+        result = result * 10 + digit;
+    }
+    Some(result)
+}
+
+/// Декодирует регистры переменной в значение заданного типа.
+///
+/// Возвращает None, если регистров меньше, чем требует тип,
+/// или данные не соответствуют типу.
+pub fn decode(data_type: DataType, byte_order: ByteOrder, registers: &[u16]) -> Option<Value> {
+    let registers = match data_type {
+        DataType::String => byte_order.swap_bytes(registers.get(..registers.len().max(1))?),
+        _ => byte_order.normalize(registers.get(..data_type.register_count() as usize)?),
+    };
+    let raw = combine(&registers);
+    let value = match data_type {
+        DataType::Bool => Value::Bool(raw != 0),
+        DataType::I16 => Value::I16(raw as u16 as i16),
+        DataType::U16 => Value::U16(raw as u16),
+        DataType::I32 => Value::I32(raw as u32 as i32),
+        DataType::U32 => Value::U32(raw as u32),
+        DataType::I64 => Value::I64(raw as i64),
+        DataType::U64 => Value::U64(raw),
+        DataType::F32 => Value::F32(f32::from_bits(raw as u32)),
+        DataType::F64 => Value::F64(f64::from_bits(raw)),
+        DataType::String => Value::String(
+            registers
+                .iter()
+                .flat_map(|register| register.to_be_bytes())
+                .take_while(|&byte| byte != 0)
+                .map(char::from)
+                .collect(),
+        ),
+        DataType::Bcd16 => Value::U16(from_bcd(raw, 4)? as u16),
+        DataType::Bcd32 => Value::U32(from_bcd(raw, 8)? as u32),
+    };
+    Some(value)
+}
+
+/// Декодирует регистры переменной с учетом битовой маски и масштабирования
+pub fn decode_item(item: &ModbusRequestItems, registers: &[u16]) -> Option<Value> {
+    if let Some(mask) = item.bit_mask {
+        let register = *item.byte_order.normalize(registers).first()?;
+        return Some(Value::Bool(register & mask != 0));
+    }
+    let value = decode(item.data_type, item.byte_order, registers)?;
+    Some(match &item.scaling {
+        Some(scaling) => value.scale(scaling),
+        None => value,
+    })
+}
+
+/// Разбирает логическое значение из текста
+pub fn parse_bool(text: &str) -> Option<bool> {
+    match &text.trim().to_lowercase()[..] {
+        "1" | "true" | "on" => Some(true),
+        "0" | "false" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Раскладывает число на count регистров, первый регистр - старшее слово
+fn split(raw: u64, count: usize) -> Vec<u16> {
+    (0..count)
+        .rev()
+        .map(|index| (raw >> (index * 16)) as u16)
+        .collect()
+}
+
+/// Переводит число в двоично-десятичный вид, None если не хватает цифр
+fn to_bcd(value: u64, digits: u32) -> Option<u64> {
+    if value >= 10u64.pow(digits) {
+        return None;
+    }
+    let mut raw = 0u64;
+    let mut rest = value;
+    for position in 0..digits {
+        raw |= (rest % 10) << (position * 4);
+        rest /= 10;
+    }
+    Some(raw)
+}
+
+/// Целое значение из текста или из уже вычисленного сырого числа с проверкой диапазона
+fn parse_int<T: FromStr + TryFrom<i128>>(text: &str, number: Option<f64>) -> Option<T> {
+    match number {
+        Some(number) if number.is_finite() => T::try_from(number.round() as i128).ok(),
+        Some(_) => None,
+        None => text.parse().ok(),
+    }
+}
+
+/// Вещественное значение из текста или из уже вычисленного сырого числа
+fn parse_float(text: &str, number: Option<f64>) -> Option<f64> {
+    let value = match number {
+        Some(number) => number,
+        None => text.parse().ok()?,
+    };
+    value.is_finite().then_some(value)
+}
+
+/// Кодирует текстовое значение переменной в регистры, обратное decode_item.
+///
+/// Битовые переменные здесь не кодируются, так как их запись
+/// зависит от текущего значения регистра.
+pub fn encode_item(item: &ModbusRequestItems, text: &str) -> Option<Vec<u16>> {
+    let count = item.count as usize;
+    let text = text.trim();
+    let number = match &item.scaling {
+        Some(scaling) if item.data_type != DataType::Bool && item.data_type != DataType::String => {
+            Some(scaling.unapply(text.parse().ok()?))
+        }
+        _ => None,
+    };
+    let raw = match item.data_type {
+        DataType::Bool => parse_bool(text)? as u64,
+        DataType::I16 => parse_int::<i16>(text, number)? as u16 as u64,
+        DataType::U16 => parse_int::<u16>(text, number)? as u64,
+        DataType::I32 => parse_int::<i32>(text, number)? as u32 as u64,
+        DataType::U32 => parse_int::<u32>(text, number)? as u64,
+        DataType::I64 => parse_int::<i64>(text, number)? as u64,
+        DataType::U64 => parse_int::<u64>(text, number)?,
+        DataType::F32 => (parse_float(text, number)? as f32).to_bits() as u64,
+        DataType::F64 => parse_float(text, number)?.to_bits(),
+        DataType::Bcd16 => to_bcd(parse_int::<u64>(text, number)?, 4)?,
+        DataType::Bcd32 => to_bcd(parse_int::<u64>(text, number)?, 8)?,
+        DataType::String => {
+            if !text.is_ascii() || text.len() > count * 2 {
+                return None;
+            }
+            let mut bytes = text.as_bytes().to_vec();
+            bytes.resize(count * 2, 0);
+            let registers = bytes
+                .chunks(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect::<Vec<u16>>();
+            return Some(item.byte_order.swap_bytes(&registers));
+        }
+    };
+    Some(item.byte_order.normalize(&split(raw, count)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::modbus_variables::{ModbusStorage, DEFAULT_POLL_INTERVAL};
+
+    #[test]
+    fn decode_integers() {
+        assert_eq!(
+            decode(DataType::U16, ByteOrder::Abcd, &[0xFFFE]),
+            Some(Value::U16(65534))
+        );
+        assert_eq!(
+            decode(DataType::I16, ByteOrder::Abcd, &[0xFFFE]),
+            Some(Value::I16(-2))
+        );
+        assert_eq!(
+            decode(DataType::U32, ByteOrder::Abcd, &[0x0001, 0x0002]),
+            Some(Value::U32(0x0001_0002))
+        );
+        assert_eq!(
+            decode(DataType::I32, ByteOrder::Abcd, &[0xFFFF, 0xFFFF]),
+            Some(Value::I32(-1))
+        );
+        assert_eq!(
+            decode(
+                DataType::U64,
+                ByteOrder::Abcd,
+                &[0x0001, 0x0002, 0x0003, 0x0004]
+            ),
+            Some(Value::U64(0x0001_0002_0003_0004))
+        );
+        assert_eq!(
+            decode(
+                DataType::I64,
+                ByteOrder::Abcd,
+                &[0xFFFF, 0xFFFF, 0xFFFF, 0xFFF6]
+            ),
+            Some(Value::I64(-10))
+        );
+    }
+
+    #[test]
+    fn decode_floats() {
+        assert_eq!(
+            decode(DataType::F32, ByteOrder::Abcd, &[0x4049, 0x0FDB]),
+            Some(Value::F32(std::f32::consts::PI))
+        );
+        assert_eq!(
+            decode(
+                DataType::F64,
+                ByteOrder::Abcd,
+                &[0x400A, 0x0000, 0x0000, 0x0000]
+            ),
+            Some(Value::F64(3.25))
+        );
+    }
+
+    #[test]
+    fn decode_bool_string_and_bcd() {
+        assert_eq!(
+            decode(DataType::Bool, ByteOrder::Abcd, &[0]),
+            Some(Value::Bool(false))
+        );
+        assert_eq!(
+            decode(DataType::Bool, ByteOrder::Abcd, &[4]),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(
+            decode(DataType::String, ByteOrder::Abcd, &[0x4142, 0x4300, 0x4400]),
+            Some(Value::String("ABC".to_string()))
+        );
+        assert_eq!(
+            decode(DataType::Bcd16, ByteOrder::Abcd, &[0x1234]),
+            Some(Value::U16(1234))
+        );
+        assert_eq!(
+            decode(DataType::Bcd32, ByteOrder::Abcd, &[0x0012, 0x3456]),
+            Some(Value::U32(123456))
+        );
+        assert_eq!(decode(DataType::Bcd16, ByteOrder::Abcd, &[0x12A4]), None);
+    }
+
+    #[test]
+    fn decode_byte_orders() {
+        let cases = [
+            (ByteOrder::Abcd, [0x4049, 0x0FDB]),
+            (ByteOrder::Cdab, [0x0FDB, 0x4049]),
+            (ByteOrder::Badc, [0x4940, 0xDB0F]),
+            (ByteOrder::Dcba, [0xDB0F, 0x4940]),
+        ];
+        for (byte_order, registers) in cases {
+            assert_eq!(
+                decode(DataType::F32, byte_order, &registers),
+                Some(Value::F32(std::f32::consts::PI))
+            );
+        }
+        assert_eq!(
+            decode(
+                DataType::U64,
+                ByteOrder::Cdab,
+                &[0x0004, 0x0003, 0x0002, 0x0001]
+            ),
+            Some(Value::U64(0x0001_0002_0003_0004))
+        );
+        assert_eq!(
+            decode(DataType::U16, ByteOrder::Badc, &[0x3412]),
+            Some(Value::U16(0x1234))
+        );
+        assert_eq!(
+            decode(DataType::String, ByteOrder::Badc, &[0x4241, 0x0043]),
+            Some(Value::String("ABC".to_string()))
+        );
+    }
+
+    #[test]
+    fn strings_ignore_word_order() {
+        let text = ModbusRequestItems {
+            count: 3,
+            ..typed_item(DataType::String, ByteOrder::Cdab)
+        };
+        assert_eq!(
+            decode(DataType::String, ByteOrder::Cdab, &[0x4142, 0x4344, 0x4546]),
+            Some(Value::String("ABCDEF".to_string()))
+        );
+        assert_eq!(
+            encode_item(&text, "ABCDEF"),
+            Some(vec![0x4142, 0x4344, 0x4546])
+        );
+        let text = ModbusRequestItems {
+            byte_order: ByteOrder::Dcba,
+            ..text
+        };
+        assert_eq!(
+            decode(DataType::String, ByteOrder::Dcba, &[0x4241, 0x4443, 0x4645]),
+            Some(Value::String("ABCDEF".to_string()))
+        );
+        assert_eq!(
+            encode_item(&text, "ABCDEF"),
+            Some(vec![0x4241, 0x4443, 0x4645])
+        );
+    }
+
+    #[test]
+    fn scale_to_engineering_units() {
+        let scaling = Scaling::from_ranges([0.0, 27648.0], [0.0, 100.0]).unwrap();
+        assert_eq!(Value::U16(13824).scale(&scaling), Value::F64(50.0));
+        let scaling = Scaling {
+            scale: 0.1,
+            offset: -40.0,
+        };
+        let value = Value::I16(655).scale(&scaling);
+        assert_eq!(value.format(Some(1), Some("°C")), "25.5 °C");
+        assert_eq!(Value::Bool(true).scale(&scaling), Value::Bool(true));
+        assert_eq!(Scaling::from_ranges([5.0, 5.0], [0.0, 100.0]), None);
+    }
+
+    #[test]
+    fn format_without_precision() {
+        assert_eq!(Value::U16(7).format(Some(2), None), "7");
+        assert_eq!(Value::F32(1.5).format(None, Some("kW")), "1.5 kW");
+    }
+
+    fn item(bit_mask: Option<u16>) -> ModbusRequestItems {
+        ModbusRequestItems {
+            storage: ModbusStorage::AO,
+            id: 1,
+            unit_id: 1,
+            name: "status".to_string(),
+            start: 0,
+            count: 1,
+            data_type: DataType::Bool,
+            byte_order: ByteOrder::Abcd,
+            scaling: None,
+            unit: None,
+            precision: None,
+            bit_mask,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    #[test]
+    fn decode_item_bits() {
+        assert_eq!(
+            decode_item(&item(Some(1 << 3)), &[0b1000]),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(
+            decode_item(&item(Some(1 << 15)), &[0x7FFF]),
+            Some(Value::Bool(false))
+        );
+        assert_eq!(
+            decode_item(&item(Some(0x00F0)), &[0x0010]),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(decode_item(&item(None), &[0x0010]), Some(Value::Bool(true)));
+    }
+
+    fn typed_item(data_type: DataType, byte_order: ByteOrder) -> ModbusRequestItems {
+        ModbusRequestItems {
+            count: data_type.register_count(),
+            data_type,
+            byte_order,
+            ..item(None)
+        }
+    }
+
+    #[test]
+    fn encode_numbers() {
+        let cases = [
+            (DataType::U16, "65534", vec![0xFFFE]),
+            (DataType::I16, "-2", vec![0xFFFE]),
+            (DataType::I32, "-1", vec![0xFFFF, 0xFFFF]),
+            (DataType::U32, "65538", vec![0x0001, 0x0002]),
+            (DataType::U64, "18446744073709551615", vec![0xFFFF; 4]),
+            (DataType::F32, "50", vec![0x4248, 0x0000]),
+            (DataType::F64, "3.25", vec![0x400A, 0x0000, 0x0000, 0x0000]),
+            (DataType::Bcd16, "1234", vec![0x1234]),
+            (DataType::Bool, "on", vec![1]),
+        ];
+        for (data_type, text, registers) in cases {
+            assert_eq!(
+                encode_item(&typed_item(data_type, ByteOrder::Abcd), text),
+                Some(registers)
+            );
+        }
+        assert_eq!(
+            encode_item(&typed_item(DataType::F32, ByteOrder::Cdab), "50"),
+            Some(vec![0x0000, 0x4248])
+        );
+        assert_eq!(
+            encode_item(&typed_item(DataType::U16, ByteOrder::Abcd), "70000"),
+            None
+        );
+        assert_eq!(
+            encode_item(&typed_item(DataType::Bcd16, ByteOrder::Abcd), "12345"),
+            None
+        );
+    }
+
+    #[test]
+    fn encode_reverses_scaling_and_strings() {
+        let scaled = ModbusRequestItems {
+            scaling: Scaling::from_ranges([0.0, 27648.0], [0.0, 100.0]),
+            ..typed_item(DataType::U16, ByteOrder::Abcd)
+        };
+        assert_eq!(encode_item(&scaled, "50"), Some(vec![13824]));
+        let text = ModbusRequestItems {
+            count: 3,
+            ..typed_item(DataType::String, ByteOrder::Abcd)
+        };
+        assert_eq!(
+            encode_item(&text, "ABC"),
+            Some(vec![0x4142, 0x4300, 0x0000])
+        );
+        assert_eq!(encode_item(&text, "TOO LONG"), None);
+    }
+
+    #[test]
+    fn decode_requires_enough_registers() {
+        assert_eq!(decode(DataType::F32, ByteOrder::Abcd, &[0x4049]), None);
+        assert_eq!(decode(DataType::U16, ByteOrder::Abcd, &[]), None);
+    }
+}
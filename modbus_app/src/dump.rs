@@ -0,0 +1,302 @@
+use std::io::{Read, Write};
+
+use crate::config_manager::channel_config::ChannelConfig;
+use crate::config_manager::modbus_variables::{ByteOrder, DataType, ModbusStorage};
+use crate::decoder::{decode, Value};
+use crate::error::{ExceptionCode, ModbusError, TaskError};
+use crate::modbus_manager::exchange;
+use crate::task::{ProtocolType, Task};
+
+/// Значения диапазона адресов и ошибки запросов, которые не удалось выполнить
+#[derive(Debug, Default)]
+pub struct Readout {
+    /// Значения по порядку адресов. Недоступные и непрочитанные адреса - None
+    pub values: Vec<Option<u16>>,
+    /// Ошибки запросов, кроме IllegalDataAddress
+    pub failures: Vec<TaskError>,
+}
+
+/// Чтение диапазона адресов одной области памяти устройства
+struct Dump<'a, S> {
+    stream: &'a mut S,
+    protocol: ProtocolType,
+    unit_id: u8,
+    storage: ModbusStorage,
+    /// Номер транзакции следующего запроса
+    id: u16,
+}
+
+impl<S: Read + Write> Dump<'_, S> {
+    /// Читает count адресов начиная со start и добавляет значения в readout.
+    /// Если устройство отвечает IllegalDataAddress, диапазон делится пополам,
+    /// пока не останутся отдельные недоступные адреса. При других ошибках
+    /// диапазон отмечается непрочитанным, чтение прерывается только ошибками,
+    /// после которых обмен по каналу связи продолжать нельзя
+    fn read(&mut self, start: u16, count: u16, readout: &mut Readout) -> Result<(), TaskError> {
+        self.id = self.id.wrapping_add(1);
+        let mut task = Task::new(
+            self.id,
+            self.unit_id,
+            self.protocol.to_owned(),
+            self.storage.read_command(),
+            start,
+            count,
+            vec![],
+        );
+        match exchange(self.stream, &mut task) {
+            Ok(data) => {
+                let data = data.unwrap_or_default();
+                let values = (0..count as usize).map(|index| data.get(index).copied());
+                readout.values.extend(values);
+                Ok(())
+            }
+            Err(TaskError {
+                error: ModbusError::Exception(ExceptionCode(0x02)),
+                ..
+            }) if count > 1 => {
+                let half = count / 2;
+                self.read(start, half, readout)?;
+                self.read(start + half, count - half, readout)
+            }
+            Err(TaskError {
+                error: ModbusError::Exception(ExceptionCode(0x02)),
+                ..
+            }) => {
+                readout.values.push(None);
+                Ok(())
+            }
+            Err(err) if err.error.breaks_connection(&self.protocol) => Err(err),
+            Err(err) => {
+                readout.values.extend(vec![None; count as usize]);
+                readout.failures.push(err);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Читает count адресов области storage начиная со start запросами
+/// наибольшего допустимого размера
+pub fn read_range<S: Read + Write>(
+    stream: &mut S,
+    protocol: ProtocolType,
+    unit_id: u8,
+    storage: ModbusStorage,
+    start: u16,
+    count: u16,
+) -> Result<Readout, TaskError> {
+    let mut dump = Dump {
+        stream,
+        protocol,
+        unit_id,
+        storage,
+        id: 0,
+    };
+    let end = start as u32 + count as u32;
+    let mut readout = Readout {
+        values: Vec::with_capacity(count as usize),
+        failures: vec![],
+    };
+    let mut address = start as u32;
+    while address < end {
+        let chunk = (end - address).min(storage.max_count() as u32) as u16;
+        dump.read(address as u16, chunk, &mut readout)?;
+        address += chunk as u32;
+    }
+    Ok(readout)
+}
+
+/// Регистр как два символа ASCII, непечатные символы заменяются точкой
+fn ascii(register: u16) -> String {
+    register
+        .to_be_bytes()
+        .iter()
+        .map(|&byte| match byte.is_ascii_graphic() || byte == b' ' {
+            true => byte as char,
+            false => '.',
+        })
+        .collect()
+}
+
+/// Вещественное число из регистра и следующего за ним. Очень большие и
+/// очень маленькие числа выводятся в экспоненциальной записи
+fn float(pair: &[Option<u16>], byte_order: ByteOrder) -> String {
+    let [Some(first), Some(second)] = pair else {
+        return String::new();
+    };
+    match decode(DataType::F32, byte_order, &[*first, *second]) {
+        Some(Value::F32(value)) if value == 0.0 || (1e-4..1e7).contains(&value.abs()) => {
+            value.to_string()
+        }
+        Some(Value::F32(value)) => format!("{value:e}"),
+        _ => String::new(),
+    }
+}
+
+/// Строки таблицы значений: для битов адрес и значение, для регистров
+/// также шестнадцатеричное, знаковое, ASCII и вещественные числа из пары
+/// регистров в порядке слов ABCD и CDAB
+fn table(storage: ModbusStorage, start: u16, values: &[Option<u16>]) -> Vec<String> {
+    let registers = matches!(storage, ModbusStorage::AI | ModbusStorage::AO);
+    let mut lines = vec![match registers {
+        true => format!(
+            "{:>6} {:>6} {:>6} {:>7} {:>5} {:>14} {:>14}",
+            "Адрес", "Hex", "Dec", "Signed", "ASCII", "Float ABCD", "Float CDAB"
+        ),
+        false => format!("{:>6} {:>9}", "Адрес", "Значение"),
+    }];
+    for (index, value) in values.iter().enumerate() {
+        let address = start as usize + index;
+        let line = match (value, registers) {
+            (None, _) => format!("{address:>6} {:>6}", "-"),
+            (Some(value), false) => format!("{address:>6} {value:>9}"),
+            (Some(value), true) => {
+                let pair = values.get(index..index + 2).unwrap_or_default();
+                format!(
+                    "{address:>6} {:>6} {value:>6} {:>7} {:>5} {:>14} {:>14}",
+                    format!("{value:04X}"),
+                    *value as i16,
+                    ascii(*value),
+                    float(pair, ByteOrder::Abcd),
+                    float(pair, ByteOrder::Cdab)
+                )
+            }
+        };
+        lines.push(line.trim_end().to_string());
+    }
+    lines
+}
+
+/// Выводит таблицу значений диапазона адресов устройства на канале из конфигурации
+pub fn run(
+    channel: &ChannelConfig,
+    unit_id: u8,
+    storage: ModbusStorage,
+    start: u16,
+    count: u16,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if count == 0 || start as u32 + count as u32 > u16::MAX as u32 + 1 {
+        Err(ModbusError::Config(format!(
+            "недопустимый диапазон адресов: начало {start}, количество {count}"
+        )))?;
+    }
+    let mut stream = channel.open()?;
+    let readout = read_range(
+        &mut stream,
+        channel.protocol_type(),
+        unit_id,
+        storage,
+        start,
+        count,
+    )
+    .map_err(|err| format!("Ошибка чтения: {err}"))?;
+    for line in table(storage, start, &readout.values) {
+        println!("{line}");
+    }
+    for err in &readout.failures {
+        println!("Не прочитано: {err}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockStream;
+
+    /// Ответ Modbus TCP на чтение регистров хранения
+    fn registers(id: u16, values: &[u16]) -> Vec<u8> {
+        let mut body = vec![0x01, 0x03, values.len() as u8 * 2];
+        body.extend(values.iter().flat_map(|value| value.to_be_bytes()));
+        ProtocolType::Tcp.encode_frame(id, &body)
+    }
+
+    /// Исключение IllegalDataAddress на чтение регистров хранения
+    fn illegal_address(id: u16) -> Vec<u8> {
+        ProtocolType::Tcp.encode_frame(id, &[0x01, 0x83, 0x02])
+    }
+
+    #[test]
+    fn unavailable_addresses_are_bisected() -> Result<(), TaskError> {
+        // Адрес 12 недоступен: 10..14 -> 10..12 и 12..14 -> 12 и 13
+        let input = [
+            illegal_address(1),
+            registers(2, &[0x4148, 0x0000]),
+            illegal_address(3),
+            illegal_address(4),
+            registers(5, &[0xFFFF]),
+        ]
+        .concat();
+        let mut stream = MockStream::new(input);
+        let readout = read_range(&mut stream, ProtocolType::Tcp, 1, ModbusStorage::AO, 10, 4)?;
+        let values = readout.values;
+        assert_eq!(values, vec![Some(0x4148), Some(0), None, Some(0xFFFF)]);
+        assert!(readout.failures.is_empty());
+        let requests = stream
+            .output
+            .chunks(12)
+            .map(|request| {
+                (
+                    u16::from_be_bytes([request[8], request[9]]),
+                    u16::from_be_bytes([request[10], request[11]]),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(requests, vec![(10, 4), (10, 2), (12, 2), (12, 1), (13, 1)]);
+        assert_eq!(
+            table(ModbusStorage::AO, 10, &values),
+            vec![
+                " Адрес    Hex    Dec  Signed ASCII     Float ABCD     Float CDAB".to_string(),
+                "    10   4148  16712   16712    AH           12.5     2.3418e-41".to_string(),
+                "    11   0000      0       0    ..".to_string(),
+                "    12      -".to_string(),
+                "    13   FFFF  65535      -1    ..".to_string(),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ranges_are_split_into_protocol_max_chunks() -> Result<(), TaskError> {
+        let input = [
+            registers(1, &[7; 125]),
+            registers(2, &[7; 125]),
+            registers(3, &[7; 50]),
+        ]
+        .concat();
+        let mut stream = MockStream::new(input);
+        let readout = read_range(&mut stream, ProtocolType::Tcp, 1, ModbusStorage::AO, 0, 300)?;
+        assert_eq!(readout.values, vec![Some(7); 300]);
+        assert_eq!(stream.output.len(), 3 * 12);
+        Ok(())
+    }
+
+    #[test]
+    fn failed_ranges_are_skipped() -> Result<(), TaskError> {
+        // Второй блок из 125 регистров отвечает исключением SlaveDeviceFailure
+        let input = [
+            registers(1, &[7; 125]),
+            ProtocolType::Tcp.encode_frame(2, &[0x01, 0x83, 0x04]),
+            registers(3, &[7; 50]),
+        ]
+        .concat();
+        let mut stream = MockStream::new(input);
+        let readout = read_range(&mut stream, ProtocolType::Tcp, 1, ModbusStorage::AO, 0, 300)?;
+        let expected = [vec![Some(7); 125], vec![None; 125], vec![Some(7); 50]].concat();
+        assert_eq!(readout.values, expected);
+        assert_eq!(readout.failures.len(), 1);
+        assert_eq!(readout.failures[0].context.start, 125);
+        assert!(matches!(
+            readout.failures[0].error,
+            ModbusError::Exception(ExceptionCode(0x04))
+        ));
+
+        // Таймаут TCP сдвигает последующие ответы, чтение прерывается
+        let mut stream = MockStream::silent(registers(1, &[7; 125]));
+        let err = read_range(&mut stream, ProtocolType::Tcp, 1, ModbusStorage::AO, 0, 300)
+            .expect_err("таймаут TCP должен прерывать чтение");
+        assert!(matches!(err.error, ModbusError::Timeout));
+        assert_eq!(err.context.start, 125);
+        Ok(())
+    }
+}
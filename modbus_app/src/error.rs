@@ -0,0 +1,199 @@
+use std::fmt::Display;
+
+use crate::task::ProtocolType;
+
+/// Код исключения, которым устройство ответило на запрос
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExceptionCode(pub u8);
+
+impl ExceptionCode {
+    /// Название исключения по спецификации modbus
+    pub fn name(&self) -> &'static str {
+        match self.0 {
+            0x01 => "IllegalFunction",
+            0x02 => "IllegalDataAddress",
+            0x03 => "IllegalDataValue",
+            0x04 => "SlaveDeviceFailure",
+            0x05 => "Acknowledge",
+            0x06 => "SlaveDeviceBusy",
+            0x07 => "NegativeAcknowledge",
+            0x08 => "MemoryParityError",
+            0x0A => "GatewayPathUnavailable",
+            0x0B => "GatewayTargetDeviceFailedToRespond",
+            _ => "UnknownException",
+        }
+    }
+}
+
+impl Display for ExceptionCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02X} {}", self.0, self.name())
+    }
+}
+
+/// Ошибка обмена с устройством modbus
+#[derive(Debug)]
+pub enum ModbusError {
+    /// Ошибка ввода-вывода канала связи
+    Transport(std::io::Error),
+    /// Устройство не ответило за отведенное время
+    Timeout,
+    /// Контрольная сумма кадра RTU не совпала
+    Crc,
+    /// Устройство ответило исключением
+    Exception(ExceptionCode),
+    /// Ответ не соответствует запросу или не разбирается
+    Frame(rmodbus::ErrorKind),
+    /// Данные не удалось преобразовать в значение переменной или обратно
+    Decode(String),
+    /// Запрос не может быть выполнен при данной конфигурации
+    Config(String),
+}
+
+impl ModbusError {
+    /// Ошибка, после которой обмен по каналу связи продолжать нельзя.
+    ///
+    /// Ответ, пришедший по TCP после таймаута, сдвинет все последующие
+    /// ответы, поэтому соединение нужно установить заново. В RTU остатки
+    /// ответа очищаются перед следующим запросом.
+    pub fn breaks_connection(&self, protocol: &ProtocolType) -> bool {
+        match self {
+            ModbusError::Transport(_) => true,
+            ModbusError::Timeout => protocol == &ProtocolType::Tcp,
+            _ => false,
+        }
+    }
+
+    /// Ошибка формирования запроса. Запрос не отправлялся, поэтому ошибка
+    /// rmodbus не должна выглядеть как исключение устройства
+    pub fn request(kind: rmodbus::ErrorKind) -> Self {
+        ModbusError::Config(format!("запрос не может быть сформирован: {kind}"))
+    }
+}
+
+impl Display for ModbusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModbusError::Transport(err) => write!(f, "ошибка канала связи: {err}"),
+            ModbusError::Timeout => write!(f, "устройство не ответило за отведенное время"),
+            ModbusError::Crc => write!(f, "ошибка контрольной суммы ответа"),
+            ModbusError::Exception(code) => write!(f, "исключение modbus {code}"),
+            ModbusError::Frame(kind) => write!(f, "некорректный ответ устройства: {kind}"),
+            ModbusError::Decode(message) => write!(f, "ошибка преобразования данных: {message}"),
+            ModbusError::Config(message) => write!(f, "ошибка конфигурации: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ModbusError {}
+
+impl From<std::io::Error> for ModbusError {
+    fn from(value: std::io::Error) -> Self {
+        match value.kind() {
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => ModbusError::Timeout,
+            _ => ModbusError::Transport(value),
+        }
+    }
+}
+
+impl From<rmodbus::ErrorKind> for ModbusError {
+    fn from(value: rmodbus::ErrorKind) -> Self {
+        use rmodbus::ErrorKind;
+        let code = match value {
+            ErrorKind::FrameCRCError => return ModbusError::Crc,
+            ErrorKind::IllegalFunction => 0x01,
+            ErrorKind::IllegalDataAddress => 0x02,
+            ErrorKind::IllegalDataValue => 0x03,
+            ErrorKind::SlaveDeviceFailure => 0x04,
+            ErrorKind::Acknowledge => 0x05,
+            ErrorKind::SlaveDeviceBusy => 0x06,
+            ErrorKind::NegativeAcknowledge => 0x07,
+            ErrorKind::MemoryParityError => 0x08,
+            ErrorKind::GatewayPathUnavailable => 0x0A,
+            ErrorKind::GatewayTargetFailed => 0x0B,
+            kind => return ModbusError::Frame(kind),
+        };
+        ModbusError::Exception(ExceptionCode(code))
+    }
+}
+
+/// Запрос, при выполнении которого произошла ошибка
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskContext {
+    pub unit_id: u8,
+    pub function: u8,
+    pub start: u16,
+    pub count: u16,
+}
+
+impl Display for TaskContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "устройство {}, функция {:02}, адрес {}, количество {}",
+            self.unit_id, self.function, self.start, self.count
+        )
+    }
+}
+
+/// Ошибка обмена вместе с запросом, к которому она относится
+#[derive(Debug)]
+pub struct TaskError {
+    pub context: TaskContext,
+    pub error: ModbusError,
+}
+
+impl TaskError {
+    pub fn new(context: TaskContext, error: impl Into<ModbusError>) -> Self {
+        Self {
+            context,
+            error: error.into(),
+        }
+    }
+}
+
+impl Display for TaskError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.error, self.context)
+    }
+}
+
+impl std::error::Error for TaskError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exception_names() {
+        assert_eq!(
+            ExceptionCode(0x02).to_string(),
+            "02 IllegalDataAddress".to_string()
+        );
+        assert_eq!(ExceptionCode(0x06).name(), "SlaveDeviceBusy");
+        assert_eq!(
+            ExceptionCode(0x0B).name(),
+            "GatewayTargetDeviceFailedToRespond"
+        );
+    }
+
+    #[test]
+    fn error_conversions() {
+        let timeout = std::io::Error::from(std::io::ErrorKind::WouldBlock);
+        assert!(matches!(ModbusError::from(timeout), ModbusError::Timeout));
+        assert!(matches!(
+            ModbusError::from(rmodbus::ErrorKind::GatewayTargetFailed),
+            ModbusError::Exception(ExceptionCode(0x0B))
+        ));
+        assert!(matches!(
+            ModbusError::from(rmodbus::ErrorKind::FrameCRCError),
+            ModbusError::Crc
+        ));
+        assert!(ModbusError::Timeout.breaks_connection(&ProtocolType::Tcp));
+        assert!(!ModbusError::Timeout.breaks_connection(&ProtocolType::Uart));
+    }
+}
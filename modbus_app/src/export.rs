@@ -0,0 +1,29 @@
+use crate::modbus_manager::PollVariable;
+
+/// Запрос записи: имя переменной и значение
+pub type WriteRequest = (String, String);
+
+/// Получатель значений переменных одного канала связи
+pub trait Export: Send {
+    /// Передает значения переменных, обновленные последним опросом
+    fn publish(&mut self, variables: &[&PollVariable]);
+}
+
+/// Вывод значений переменных в консоль
+pub struct Console;
+
+impl Export for Console {
+    fn publish(&mut self, variables: &[&PollVariable]) {
+        for variable in variables {
+            let item = variable.item();
+            match variable.value() {
+                Some(value) => println!(
+                    "{}: {}",
+                    item.name,
+                    value.format(item.precision, item.unit.as_deref())
+                ),
+                None => println!("{}: нет данных", item.name),
+            }
+        }
+    }
+}
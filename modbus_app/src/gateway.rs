@@ -0,0 +1,302 @@
+use rmodbus::ErrorKind;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+
+use crate::config_manager::channel_config::{ChannelConfig, ChannelRtu, Connect};
+use crate::connection::Connection;
+use crate::error::ModbusError;
+use crate::task::{rtu_response_len, ProtocolType};
+
+/// Шлюз Modbus TCP - RTU.
+///
+/// Принимает запросы клиентов Modbus TCP и передает их устройствам на
+/// последовательной шине. Шина занимается на время одного запроса, поэтому
+/// запросы разных клиентов выполняются по очереди.
+pub struct Gateway<C, S> {
+    bus: Arc<Mutex<Connection<C, S>>>,
+}
+
+impl<C, S> Clone for Gateway<C, S> {
+    fn clone(&self) -> Self {
+        Self {
+            bus: self.bus.clone(),
+        }
+    }
+}
+
+impl<C, S> Gateway<C, S>
+where
+    C: Connect<Output = Result<S, Box<dyn std::error::Error>>> + Send + 'static,
+    S: Read + Write + Send + 'static,
+{
+    pub fn new(connection: Connection<C, S>) -> Self {
+        Self {
+            bus: Arc::new(Mutex::new(connection)),
+        }
+    }
+
+    /// Принимает подключения клиентов, каждый клиент обслуживается в своем потоке
+    pub fn serve(&self, listener: TcpListener) -> std::io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let gateway = self.clone();
+            std::thread::spawn(move || {
+                let peer = stream
+                    .peer_addr()
+                    .map(|addr| addr.to_string())
+                    .unwrap_or_default();
+                if let Err(err) = gateway.handle_client(stream) {
+                    println!("Ошибка обмена с клиентом {peer}: {err}");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Обрабатывает запросы одного клиента до закрытия соединения
+    fn handle_client(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        loop {
+            let mut request = vec![0u8; ProtocolType::Tcp.head_len()];
+            match stream.read_exact(&mut request) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err),
+            }
+            let len = u16::from_be_bytes([request[4], request[5]]) as usize;
+            if !(2..=254).contains(&len) {
+                return Err(invalid_data(ErrorKind::FrameBroken));
+            }
+            request.resize(request.len() + len, 0);
+            stream.read_exact(&mut request[6..])?;
+            let id = u16::from_be_bytes([request[0], request[1]]);
+            let body = ProtocolType::Tcp
+                .decode_frame(&request)
+                .map_err(invalid_data)?;
+            let reply = match self.forward(body) {
+                Ok(Some(reply)) => reply,
+                // На широковещательный запрос устройства не отвечают
+                Ok(None) => continue,
+                Err(err) => {
+                    println!("Ошибка запроса к устройству {}: {err}", body[0]);
+                    let code = match err {
+                        ModbusError::Transport(_) => 0x0A,
+                        _ => 0x0B,
+                    };
+                    vec![body[0], body[1] | 0x80, code]
+                }
+            };
+            stream.write_all(&ProtocolType::Tcp.encode_frame(id, &reply))?;
+        }
+    }
+
+    /// Выполняет запрос на шине, при ошибке канала связи шина переподключается
+    fn forward(&self, body: &[u8]) -> Result<Option<Vec<u8>>, ModbusError> {
+        let mut connection = self.bus.lock().unwrap_or_else(PoisonError::into_inner);
+        // Клиент получает ответ сразу, а не после паузы перед переподключением
+        if connection.is_waiting() {
+            return Err(not_connected());
+        }
+        let stream = connection.stream().ok_or_else(not_connected)?;
+        let result = exchange_rtu(stream, body);
+        match &result {
+            Err(err) if err.breaks_connection(&ProtocolType::Uart) => {
+                connection.disconnect(err.to_string());
+            }
+            Err(_) => {}
+            Ok(_) => connection.confirm(),
+        }
+        result
+    }
+}
+
+fn invalid_data(err: ErrorKind) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+}
+
+fn not_connected() -> ModbusError {
+    ModbusError::Transport(std::io::Error::new(
+        std::io::ErrorKind::NotConnected,
+        "нет соединения с шиной",
+    ))
+}
+
+/// Отправляет адрес устройства и PDU кадром RTU и возвращает ответ без
+/// контрольной суммы. На широковещательный запрос ответ не ожидается.
+fn exchange_rtu<S: Read + Write>(
+    stream: &mut S,
+    body: &[u8],
+) -> Result<Option<Vec<u8>>, ModbusError> {
+    stream.write_all(&ProtocolType::Uart.encode_frame(0, body))?;
+    stream.flush()?;
+    if body[0] == 0 {
+        return Ok(None);
+    }
+    let mut frame = vec![0u8; ProtocolType::Uart.head_len()];
+    stream.read_exact(&mut frame)?;
+    // Длина некоторых ответов уточняется по мере приема
+    loop {
+        let len = rtu_response_len(&body[1..], &frame)?;
+        if len <= frame.len() {
+            break;
+        }
+        let received = frame.len();
+        frame.resize(len, 0);
+        stream.read_exact(&mut frame[received..])?;
+    }
+    let reply = ProtocolType::Uart.decode_frame(&frame)?;
+    if reply[0] != body[0] || reply[1] & 0x7F != body[1] {
+        return Err(ModbusError::Frame(ErrorKind::FrameBroken));
+    }
+    Ok(Some(reply.to_vec()))
+}
+
+/// Запускает шлюз к каналу RTU из конфигурации
+pub fn run(listen: &str, channel: &ChannelConfig) -> Result<(), Box<dyn std::error::Error>> {
+    if channel.protocol_type() != ProtocolType::Uart {
+        Err(ModbusError::Config(format!(
+            "шлюз работает только с каналом Uart, канал {} - {:?}",
+            channel.name(),
+            channel.protocol_type()
+        )))?;
+    }
+    let (events, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for event in receiver {
+            println!("{event}");
+        }
+    });
+    let mut connection = Connection::new(
+        channel.name(),
+        ChannelRtu::from(channel.to_owned()),
+        channel.backoff(),
+    );
+    connection.subscribe(events);
+    let listener = TcpListener::bind(listen)?;
+    println!(
+        "Шлюз к каналу {} ожидает подключений: {}",
+        channel.name(),
+        listener.local_addr()?
+    );
+    Gateway::new(connection).serve(listener)?;
+    Ok(())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::connection::Backoff;
+    use crate::error::ExceptionCode;
+    use crate::modbus_manager::exchange;
+    use crate::task::{CommandType, Task};
+    use serialport::{SerialPort, TTYPort};
+    use std::time::Duration;
+
+    #[test]
+    fn gateway_forwards_to_rtu() -> Result<(), Box<dyn std::error::Error>> {
+        let (mut master, slave) = TTYPort::pair()?;
+        let config: ChannelConfig = serde_yaml::from_str(&format!(
+            "{{protocol: Uart, path: {}, baud_rate: 19200, timeout: 0.3}}",
+            slave.name().ok_or("pty without name")?
+        ))?;
+        let backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(1), 0.0);
+        let connection = Connection::new(config.name(), ChannelRtu::from(config), backoff);
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        std::thread::spawn(move || Gateway::new(connection).serve(listener));
+        master.set_timeout(Duration::from_secs(1))?;
+        // Устройство отвечает на первый запрос и молчит на второй
+        let device = std::thread::spawn(move || -> std::io::Result<(TTYPort, Vec<u8>)> {
+            let mut request = [0u8; 8];
+            master.read_exact(&mut request)?;
+            master.write_all(&[0x01, 0x03, 0x02, 0x01, 0x2C, 0xB8, 0x09])?;
+            let mut ignored = [0u8; 8];
+            master.read_exact(&mut ignored)?;
+            Ok((master, request.to_vec()))
+        });
+        let mut client = TcpStream::connect(addr)?;
+        client.set_read_timeout(Some(Duration::from_secs(2)))?;
+        let mut task = Task::new(
+            0x1234,
+            1,
+            ProtocolType::Tcp,
+            CommandType::ReadHoldingRegisters,
+            10,
+            1,
+            vec![],
+        );
+        assert_eq!(exchange(&mut client, &mut task)?, Some(vec![300]));
+        task.set_id(0x1235);
+        let err = exchange(&mut client, &mut task).unwrap_err();
+        assert!(matches!(
+            err.error,
+            ModbusError::Exception(ExceptionCode(0x0B))
+        ));
+        let (_master, request) = device.join().map_err(|_| "device thread panicked")??;
+        assert_eq!(
+            request,
+            vec![0x01, 0x03, 0x00, 0x0A, 0x00, 0x01, 0xA4, 0x08]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn gateway_forwards_non_standard_functions() -> Result<(), Box<dyn std::error::Error>> {
+        let (mut master, slave) = TTYPort::pair()?;
+        let config: ChannelConfig = serde_yaml::from_str(&format!(
+            "{{protocol: Uart, path: {}, baud_rate: 19200, timeout: 0.3}}",
+            slave.name().ok_or("pty without name")?
+        ))?;
+        let backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(1), 0.0);
+        let connection = Connection::new(config.name(), ChannelRtu::from(config), backoff);
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        std::thread::spawn(move || Gateway::new(connection).serve(listener));
+        master.set_timeout(Duration::from_secs(1))?;
+        // Длина ответов FC 17 и FC 43/14 определяется по их содержимому
+        let device = std::thread::spawn(move || -> std::io::Result<TTYPort> {
+            let mut request = [0u8; 4];
+            master.read_exact(&mut request)?;
+            master
+                .write_all(&ProtocolType::Uart.encode_frame(0, &[0x01, 0x11, 0x02, 0x2A, 0xFF]))?;
+            let mut request = [0u8; 7];
+            master.read_exact(&mut request)?;
+            master.write_all(&ProtocolType::Uart.encode_frame(
+                0,
+                &[
+                    0x01, 0x2B, 0x0E, 0x01, 0x01, 0x00, 0x00, 0x01, 0x00, 0x04, b'A', b'C', b'M',
+                    b'E',
+                ],
+            ))?;
+            Ok(master)
+        });
+        let mut client = TcpStream::connect(addr)?;
+        client.set_read_timeout(Some(Duration::from_secs(2)))?;
+        let mut task = Task::new(
+            1,
+            1,
+            ProtocolType::Tcp,
+            CommandType::ReportServerId,
+            0,
+            0,
+            vec![],
+        );
+        assert_eq!(exchange(&mut client, &mut task)?, Some(vec![0x2A, 0xFF]));
+        let mut task = Task::new(
+            2,
+            1,
+            ProtocolType::Tcp,
+            CommandType::ReadDeviceIdentification {
+                code: 1,
+                object_id: 0,
+            },
+            0,
+            0,
+            vec![],
+        );
+        let result = exchange(&mut client, &mut task)?.ok_or("no data")?;
+        assert_eq!(&result[result.len() - 4..], &[0x41, 0x43, 0x4D, 0x45]);
+        device.join().map_err(|_| "device thread panicked")??;
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+use rmodbus::ErrorKind;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use crate::config_manager::channel_config::ChannelConfig;
+use crate::error::{ExceptionCode, ModbusError, TaskError};
+use crate::modbus_manager::exchange;
+use crate::task::{CommandType, DeviceIdentification, ProtocolType, Task};
+
+/// Код чтения одного объекта FC 43/14
+const READ_OBJECT: u8 = 4;
+
+/// Набор объектов идентификации устройства
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    /// VendorName, ProductCode, MajorMinorRevision
+    Basic = 1,
+    /// Основные объекты, VendorUrl, ProductName, ModelName, UserApplicationName
+    Regular = 2,
+    /// Обычные объекты и объекты производителя с номерами от 0x80
+    Extended = 3,
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match &value.to_lowercase()[..] {
+            "basic" | "1" => Ok(Level::Basic),
+            "regular" | "2" => Ok(Level::Regular),
+            "extended" | "3" => Ok(Level::Extended),
+            _ => Err(format!(
+                "неизвестный набор объектов {value}, ожидается basic, regular или extended"
+            )),
+        }
+    }
+}
+
+/// Запросы идентификации одного устройства
+struct Identify<'a, S> {
+    stream: &'a mut S,
+    protocol: ProtocolType,
+    unit_id: u8,
+    /// Номер транзакции следующего запроса
+    id: u16,
+}
+
+impl<S: Read + Write> Identify<'_, S> {
+    fn request(&mut self, code: u8, object_id: u8) -> Result<DeviceIdentification, TaskError> {
+        self.id = self.id.wrapping_add(1);
+        let mut task = Task::new(
+            self.id,
+            self.unit_id,
+            self.protocol.to_owned(),
+            CommandType::ReadDeviceIdentification { code, object_id },
+            0,
+            0,
+            vec![],
+        );
+        let data = exchange(self.stream, &mut task)?.unwrap_or_default();
+        let bytes = data.iter().map(|&byte| byte as u8).collect::<Vec<_>>();
+        DeviceIdentification::parse(&bytes)
+            .ok_or_else(|| TaskError::new(task.context(), ErrorKind::FrameBroken))
+    }
+
+    /// Читает все объекты набора, продолжая чтение, пока устройство сообщает,
+    /// что объекты не поместились в ответ
+    fn read_level(&mut self, level: Level) -> Result<DeviceIdentification, TaskError> {
+        let mut result = self.request(level as u8, 0)?;
+        while result.more_follows {
+            // Номер следующего объекта должен расти, иначе чтение не закончится
+            let last = result.objects.last().map(|(id, _)| *id).unwrap_or(0);
+            if result.next_object_id <= last {
+                break;
+            }
+            let next = self.request(level as u8, result.next_object_id)?;
+            result.objects.extend(next.objects);
+            result.more_follows = next.more_follows;
+            result.next_object_id = next.next_object_id;
+        }
+        Ok(result)
+    }
+}
+
+/// Читает идентификацию устройства. Без указания набора читается
+/// расширенный набор, а если устройство его не поддерживает - меньшие
+pub fn identify<S: Read + Write>(
+    stream: &mut S,
+    protocol: ProtocolType,
+    unit_id: u8,
+    level: Option<Level>,
+) -> Result<DeviceIdentification, TaskError> {
+    let mut identify = Identify {
+        stream,
+        protocol,
+        unit_id,
+        id: 0,
+    };
+    let levels = match level {
+        Some(level) => vec![level],
+        None => vec![Level::Extended, Level::Regular, Level::Basic],
+    };
+    let (last, levels) = levels.split_last().unwrap_or((&Level::Basic, &[]));
+    for level in levels {
+        match identify.read_level(*level) {
+            Err(TaskError {
+                error: ModbusError::Exception(ExceptionCode(0x02 | 0x03)),
+                ..
+            }) => continue,
+            result => return result,
+        }
+    }
+    identify.read_level(*last)
+}
+
+/// Читает один объект идентификации устройства
+pub fn read_object<S: Read + Write>(
+    stream: &mut S,
+    protocol: ProtocolType,
+    unit_id: u8,
+    object_id: u8,
+) -> Result<DeviceIdentification, TaskError> {
+    Identify {
+        stream,
+        protocol,
+        unit_id,
+        id: 0,
+    }
+    .request(READ_OBJECT, object_id)
+}
+
+/// Название объекта идентификации по спецификации modbus
+fn object_name(id: u8) -> String {
+    match id {
+        0x00 => "VendorName".to_string(),
+        0x01 => "ProductCode".to_string(),
+        0x02 => "MajorMinorRevision".to_string(),
+        0x03 => "VendorUrl".to_string(),
+        0x04 => "ProductName".to_string(),
+        0x05 => "ModelName".to_string(),
+        0x06 => "UserApplicationName".to_string(),
+        _ => format!("Объект 0x{id:02X}"),
+    }
+}
+
+/// Значение объекта: текст, если он состоит из печатных символов, иначе байты
+fn object_value(value: &[u8]) -> String {
+    match std::str::from_utf8(value) {
+        Ok(text) if !text.chars().any(char::is_control) => text.to_string(),
+        _ => value
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// Уровень соответствия устройства: набор объектов и поддержка чтения
+/// отдельных объектов
+fn conformity(level: u8) -> String {
+    let objects = match level & 0x7F {
+        0x01 => "основные объекты",
+        0x02 => "обычные объекты",
+        0x03 => "расширенные объекты",
+        _ => "неизвестный набор объектов",
+    };
+    match level & 0x80 {
+        0 => objects.to_string(),
+        _ => format!("{objects}, чтение отдельных объектов"),
+    }
+}
+
+/// Выводит идентификацию устройства на канале из конфигурации
+pub fn run(
+    channel: &ChannelConfig,
+    unit_id: u8,
+    level: Option<Level>,
+    object: Option<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stream = channel.open()?;
+    let protocol = channel.protocol_type();
+    let result = match object {
+        Some(object) => read_object(&mut stream, protocol, unit_id, object),
+        None => identify(&mut stream, protocol, unit_id, level),
+    }
+    .map_err(|err| format!("Ошибка чтения идентификации: {err}"))?;
+    println!(
+        "Устройство {unit_id}, уровень соответствия {:02X}: {}",
+        result.conformity,
+        conformity(result.conformity)
+    );
+    for (id, value) in &result.objects {
+        println!("{}: {}", object_name(*id), object_value(value));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockStream;
+
+    /// Ответ Modbus TCP на FC 43/14 с кодом чтения code
+    fn response(id: u16, code: u8, more: u8, next: u8, objects: &[(u8, &str)]) -> Vec<u8> {
+        let mut body = vec![
+            0x01,
+            0x2B,
+            0x0E,
+            code,
+            0x83,
+            more,
+            next,
+            objects.len() as u8,
+        ];
+        for (object, value) in objects {
+            body.extend([*object, value.len() as u8]);
+            body.extend(value.as_bytes());
+        }
+        ProtocolType::Tcp.encode_frame(id, &body)
+    }
+
+    #[test]
+    fn identification_continues_while_more_follows() -> Result<(), TaskError> {
+        let input = [
+            response(1, 3, 0xFF, 0x02, &[(0x00, "ACME"), (0x01, "PX-100")]),
+            response(2, 3, 0x00, 0x00, &[(0x02, "V1.2"), (0x80, "line 4")]),
+        ]
+        .concat();
+        let mut stream = MockStream::new(input);
+        let result = identify(&mut stream, ProtocolType::Tcp, 1, Some(Level::Extended))?;
+        let objects = result
+            .objects
+            .iter()
+            .map(|(id, value)| (object_name(*id), object_value(value)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            objects,
+            vec![
+                ("VendorName".to_string(), "ACME".to_string()),
+                ("ProductCode".to_string(), "PX-100".to_string()),
+                ("MajorMinorRevision".to_string(), "V1.2".to_string()),
+                ("Объект 0x80".to_string(), "line 4".to_string()),
+            ]
+        );
+        // Второй запрос продолжает чтение со следующего объекта
+        assert_eq!(&stream.output[17..], &[0x01, 0x2B, 0x0E, 0x03, 0x02]);
+        Ok(())
+    }
+
+    #[test]
+    fn identification_falls_back_to_supported_level() -> Result<(), TaskError> {
+        let input = [
+            ProtocolType::Tcp.encode_frame(1, &[0x01, 0xAB, 0x03]),
+            ProtocolType::Tcp.encode_frame(2, &[0x01, 0xAB, 0x03]),
+            response(3, 1, 0x00, 0x00, &[(0x00, "ACME")]),
+        ]
+        .concat();
+        let mut stream = MockStream::new(input);
+        let result = identify(&mut stream, ProtocolType::Tcp, 1, None)?;
+        assert_eq!(result.objects, vec![(0x00, b"ACME".to_vec())]);
+        let codes = stream
+            .output
+            .chunks(11)
+            .map(|request| request[9])
+            .collect::<Vec<_>>();
+        assert_eq!(codes, vec![3, 2, 1]);
+        assert_eq!(object_value(&[0x01, 0xFF]), "01 FF");
+        Ok(())
+    }
+}
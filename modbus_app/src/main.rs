@@ -1,80 +1,513 @@
-use std::io::{ErrorKind, Write};
+use std::{
+    io::{Read, Write},
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+use clap::Parser;
 
 use crate::{
-    cmd::get_path,
+    cmd::{Args, Command},
     config_manager::{
-        channel_config::{ChannelTcp, Connect},
+        channel_config::{ChannelConfig, ChannelRtu, ChannelTcp, Connect},
+        modbus_variables::{ConfigItem, ModbusRequestItems},
         Config,
     },
+    connection::{Connection, ConnectionState, StateChange},
+    error::TaskError,
+    export::{Console, Export, WriteRequest},
+    modbus_manager::ModbusManager,
+    task::ProtocolType,
 };
 
 mod cmd;
 mod config_manager;
+mod connection;
+mod decoder;
+mod dump;
+mod error;
+mod export;
+mod gateway;
+mod identify;
+#[cfg(test)]
+mod mock;
 mod modbus_manager;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod planner;
+mod scan;
+mod scheduler;
+mod simulator;
 mod task;
+mod transaction;
+mod writer;
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let configs = Config::try_read_config_file(get_path())?;
-    let modbus_tcp_config = ChannelTcp::from(configs.channel().to_owned());
-    loop {
-        let mut stream = match modbus_tcp_config.connect() {
-            Ok(stream) => {
-                println!(
-                    "Установлено соединение с клиентом: {}",
-                    modbus_tcp_config.url()
-                );
-                Some(stream)
+    let args = Args::parse();
+    match args.command() {
+        Some(Command::Simulator { listen, data }) => simulator::run(listen, data.to_owned()),
+        Some(Command::Validate) => validate_config(args.get_path()),
+        Some(Command::Gateway { listen, channel }) => {
+            let configs = Config::try_read_config_file(args.get_path())?;
+            gateway::run(listen, configs.find_channel(channel.as_deref())?)
+        }
+        Some(Command::Scan {
+            from,
+            to,
+            probe,
+            timeout,
+            channel,
+        }) => {
+            let configs = Config::try_read_config_file(args.get_path())?;
+            scan::run(
+                configs.find_channel(channel.as_deref())?,
+                *from,
+                *to,
+                *probe,
+                *timeout,
+            )
+        }
+        Some(Command::Dump {
+            storage,
+            start,
+            count,
+            unit_id,
+            channel,
+        }) => {
+            let configs = Config::try_read_config_file(args.get_path())?;
+            dump::run(
+                configs.find_channel(channel.as_deref())?,
+                *unit_id,
+                *storage,
+                *start,
+                *count,
+            )
+        }
+        Some(Command::Identify {
+            unit_id,
+            level,
+            object,
+            channel,
+        }) => {
+            let configs = Config::try_read_config_file(args.get_path())?;
+            identify::run(
+                configs.find_channel(channel.as_deref())?,
+                *unit_id,
+                *level,
+                *object,
+            )
+        }
+        Some(Command::Write {
+            name,
+            storage,
+            address,
+            unit_id,
+            data_type,
+            channel,
+            value,
+        }) => {
+            let configs = Config::try_read_config_file(args.get_path())?;
+            let item = match (name, storage, address) {
+                (Some(name), _, _) => configs
+                    .variables()
+                    .iter()
+                    .find(|item| &item.name == name)
+                    .ok_or(format!("Переменная {name} не найдена в конфигурации"))?
+                    .to_owned(),
+                (None, Some(storage), Some(address)) => ConfigItem {
+                    storage: Some(*storage),
+                    id: 1,
+                    unit_id: *unit_id,
+                    name: format!("{storage:?}:{address}"),
+                    start: Some(*address),
+                    address: None,
+                    data_type: *data_type,
+                    length: None,
+                    byte_order: None,
+                    scale: None,
+                    offset: None,
+                    raw_range: None,
+                    eng_range: None,
+                    unit: None,
+                    precision: None,
+                    bit: None,
+                    mask: None,
+                    poll_interval: None,
+                    group: None,
+                    channel: channel.to_owned(),
+                },
+                _ => Err("Укажите имя переменной или область памяти и адрес")?,
+            };
+            write_variable(configs.find_channel(item.channel.as_deref())?, item, value)
+        }
+        None => poll_variables(args.get_path()),
+    }
+}
+
+/// Выводит все ошибки файла конфигурации
+fn validate_config(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    let source = std::fs::read_to_string(&path)?;
+    match Config::parse(&source) {
+        Ok(_) => {
+            println!("Конфигурация {} не содержит ошибок", path.display());
+            Ok(())
+        }
+        Err(diagnostics) => {
+            for diagnostic in &diagnostics {
+                println!("{}: {diagnostic}", path.display());
             }
-            Err(err) => {
-                println!(
-                    "Ошибка установки соединения с клиентом {}: {err}",
-                    modbus_tcp_config.url()
-                );
-                None
+            Err(format!("Найдено ошибок: {}", diagnostics.len()))?
+        }
+    }
+}
+
+/// Однократная запись значения переменной с выводом ответа устройства
+fn write_variable(
+    channel: &ChannelConfig,
+    item: ConfigItem,
+    value: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let item = ModbusRequestItems::try_from(item)?;
+    let mut stream = channel.open()?;
+    writer::write_item(&mut stream, &item, channel.protocol_type(), value)
+        .map_err(|err| format!("Ошибка записи {}: {err}", item.name))?;
+    println!("Запись {} = {value} подтверждена устройством", item.name);
+    Ok(())
+}
+
+/// Циклический опрос переменных из файла конфигурации.
+///
+/// Каждый канал связи опрашивается в отдельном потоке и переподключается
+/// независимо от остальных. При сборке с функцией async каналы TCP
+/// опрашиваются задачами одной среды tokio в основном потоке. При сборке с
+/// функцией mqtt значения дополнительно публикуются в брокер, если он описан
+/// в конфигурации.
+fn poll_variables(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    let configs = Config::try_read_config_file(path)?;
+    let (events, receiver) = channel::<StateChange>();
+    std::thread::spawn(move || {
+        for event in receiver {
+            println!("{event}");
+        }
+    });
+    let mut handles = vec![];
+    #[cfg(feature = "async")]
+    let mut async_channels = vec![];
+    for channel in configs.channels() {
+        let items = configs.channel_variables(channel);
+        if items.is_empty() {
+            println!("Канал {} не содержит переменных", channel.name());
+            continue;
+        }
+        #[cfg_attr(not(feature = "mqtt"), allow(unused_mut))]
+        let mut exports: Vec<Box<dyn Export>> = vec![Box::new(Console)];
+        #[cfg_attr(not(feature = "mqtt"), allow(unused_mut))]
+        let mut subscribers = vec![events.to_owned()];
+        #[cfg_attr(not(feature = "mqtt"), allow(unused_variables))]
+        let (write_sender, writes) = std::sync::mpsc::channel::<WriteRequest>();
+        #[cfg(feature = "mqtt")]
+        if let Some(config) = configs.mqtt() {
+            let export = mqtt::MqttExport::start(config, &channel.name(), &items, write_sender)?;
+            subscribers.push(export.status_events());
+            exports.push(Box::new(export));
+        }
+        let manager = ModbusManager::new(
+            &items,
+            channel.protocol_type(),
+            channel.max_gap.unwrap_or(0),
+        )?
+        .with_window(channel.window());
+        let channel = channel.to_owned();
+        #[cfg(feature = "async")]
+        if channel.protocol_type() == ProtocolType::Tcp {
+            async_channels.push((channel, manager, subscribers, exports, writes));
+            continue;
+        }
+        handles.push(std::thread::spawn(move || {
+            poll_channel(channel, manager, subscribers, exports, writes)
+        }));
+    }
+    #[cfg(feature = "async")]
+    if !async_channels.is_empty() {
+        return poll_channels_async(async_channels);
+    }
+    if handles.is_empty() {
+        Err("В конфигурации нет переменных для опроса")?;
+    }
+    for handle in handles {
+        handle
+            .join()
+            .map_err(|_| "Поток опроса канала завершился аварийно")?;
+    }
+    Ok(())
+}
+
+/// Канал связи и всё, что нужно для его опроса
+#[cfg(feature = "async")]
+type AsyncChannel = (
+    ChannelConfig,
+    ModbusManager,
+    Vec<Sender<StateChange>>,
+    Vec<Box<dyn Export>>,
+    Receiver<WriteRequest>,
+);
+
+/// Опрос каналов TCP задачами однопоточной среды tokio
+#[cfg(feature = "async")]
+fn poll_channels_async(channels: Vec<AsyncChannel>) -> Result<(), Box<dyn std::error::Error>> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let tasks = channels
+            .into_iter()
+            .map(|(channel, manager, subscribers, exports, writes)| {
+                tokio::spawn(poll_channel_async(
+                    channel,
+                    manager,
+                    subscribers,
+                    exports,
+                    writes,
+                ))
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            task.await?;
+        }
+        Ok(())
+    })
+}
+
+/// Асинхронный опрос переменных одного канала TCP
+#[cfg(feature = "async")]
+async fn poll_channel_async(
+    channel: ChannelConfig,
+    mut manager: ModbusManager,
+    subscribers: Vec<Sender<StateChange>>,
+    mut exports: Vec<Box<dyn Export>>,
+    writes: Receiver<WriteRequest>,
+) {
+    // Запросы записи поступают из потоков получателей значений и
+    // пересылаются в очередь, ожидание которой не блокирует среду tokio
+    let (sender, mut writes_async) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for request in writes {
+            if sender.send(request).is_err() {
+                break;
             }
+        }
+    });
+    let mut woken = None;
+    let name = channel.name();
+    let backoff = channel.backoff();
+    let channel = ChannelTcp::from(channel);
+    let timeout = channel.timeout().to_owned();
+    let mut connection = Connection::new(name, channel, backoff);
+    subscribers
+        .into_iter()
+        .for_each(|subscriber| connection.subscribe(subscriber));
+    loop {
+        let reconnected = connection.state() != ConnectionState::Connected;
+        let Some(stream) = connection.stream_async().await else {
+            continue;
         };
-        let buf = [0u8; 256];
-        loop {
-            match &stream {
-                Some(tcp_stream) => match tcp_stream.take_error() {
-                    Ok(err) => match err {
-                        Some(err) => match err.kind() {
-                            ErrorKind::BrokenPipe
-                            | ErrorKind::ConnectionAborted
-                            | ErrorKind::ConnectionRefused
-                            | ErrorKind::ConnectionReset => {
-                                println!("Ошибка в канале связи {err}");
-                                stream = None;
-                                break;
-                            }
-                            _ => {
-                                println!("Ошибка в канале связи {err}");
-                                continue;
-                            }
-                        },
-                        None => println!("Нет ошибок в канале связи"),
-                    },
-                    Err(err) => {
-                        println!(
-                            "Ошибка получения ошибки из канала связи {},{err}",
-                            modbus_tcp_config.url()
-                        );
-                        stream = None;
-                        break;
-                    }
-                },
-                None => break,
+        if reconnected {
+            manager.restart();
+        }
+        let requests = woken
+            .take()
+            .into_iter()
+            .chain(std::iter::from_fn(|| writes_async.try_recv().ok()));
+        let result = match write_requests_async(stream, &manager, requests.collect(), timeout).await
+        {
+            Ok(()) => manager.poll_async(stream, timeout).await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(()) => {
+                connection.confirm();
+                let updated = manager.take_updated();
+                exports
+                    .iter_mut()
+                    .for_each(|export| export.publish(&updated));
+                woken = manager.wait_async(&mut writes_async).await;
             }
-            match stream {
-                Some(ref mut tcp_stream) => match tcp_stream.write(&buf) {
-                    Ok(size) => println!("Записано байт:{size}"),
-                    Err(err) => {
-                        println!("Ошибка записи:{err}");
-                        break;
-                    }
-                },
-                None => {}
+            Err(err) => connection.disconnect(err.to_string()),
+        }
+    }
+}
+
+/// Асинхронный вариант write_requests
+#[cfg(feature = "async")]
+async fn write_requests_async<S>(
+    stream: &mut S,
+    manager: &ModbusManager,
+    requests: Vec<WriteRequest>,
+    timeout: std::time::Duration,
+) -> Result<(), TaskError>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    for (name, value) in requests {
+        let Some(variable) = manager.variable(&name) else {
+            println!("Переменная {name} не найдена в конфигурации");
+            continue;
+        };
+        let protocol = manager.protocol().to_owned();
+        let result =
+            writer::write_item_async(stream, variable.item(), protocol, &value, timeout).await;
+        report_write(manager, &name, &value, result)?;
+    }
+    Ok(())
+}
+
+/// Опрос переменных одного канала связи
+fn poll_channel(
+    channel: ChannelConfig,
+    mut manager: ModbusManager,
+    subscribers: Vec<Sender<StateChange>>,
+    mut exports: Vec<Box<dyn Export>>,
+    writes: Receiver<WriteRequest>,
+) -> ! {
+    let protocol = channel.protocol_type();
+    let name = channel.name();
+    let backoff = channel.backoff();
+    match protocol {
+        ProtocolType::Tcp => {
+            let mut connection = Connection::new(name, ChannelTcp::from(channel), backoff);
+            subscribers
+                .into_iter()
+                .for_each(|subscriber| connection.subscribe(subscriber));
+            run(&mut connection, &mut manager, &mut exports, &writes)
+        }
+        ProtocolType::Uart => {
+            let mut connection = Connection::new(name, ChannelRtu::from(channel), backoff);
+            subscribers
+                .into_iter()
+                .for_each(|subscriber| connection.subscribe(subscriber));
+            run(&mut connection, &mut manager, &mut exports, &writes)
+        }
+    }
+}
+
+/// Опрашивает переменные, переподключаясь к каналу связи при ошибках.
+/// Запросы записи выполняются перед очередным опросом, а поступившие во
+/// время ожидания - сразу, не дожидаясь срока опроса.
+fn run<C, S>(
+    connection: &mut Connection<C, S>,
+    manager: &mut ModbusManager,
+    exports: &mut [Box<dyn Export>],
+    writes: &Receiver<WriteRequest>,
+) -> !
+where
+    C: Connect<Output = Result<S, Box<dyn std::error::Error>>>,
+    S: Read + Write,
+{
+    let mut woken = None;
+    loop {
+        let reconnected = connection.state() != ConnectionState::Connected;
+        let Some(stream) = connection.stream() else {
+            continue;
+        };
+        if reconnected {
+            manager.restart();
+        }
+        let requests = woken.take().into_iter().chain(writes.try_iter());
+        match write_requests(stream, manager, requests).and_then(|_| manager.poll(stream)) {
+            Ok(()) => {
+                connection.confirm();
+                let updated = manager.take_updated();
+                exports
+                    .iter_mut()
+                    .for_each(|export| export.publish(&updated));
+                woken = manager.wait(writes);
             }
+            Err(err) => connection.disconnect(err.to_string()),
         }
-        std::thread::sleep(modbus_tcp_config.timeout().to_owned());
+    }
+}
+
+/// Выполняет запросы записи, поступившие от получателей значений
+fn write_requests<S: Read + Write>(
+    stream: &mut S,
+    manager: &ModbusManager,
+    requests: impl Iterator<Item = WriteRequest>,
+) -> Result<(), TaskError> {
+    for (name, value) in requests {
+        let Some(variable) = manager.variable(&name) else {
+            println!("Переменная {name} не найдена в конфигурации");
+            continue;
+        };
+        let protocol = manager.protocol().to_owned();
+        let result = writer::write_item(stream, variable.item(), protocol, &value);
+        report_write(manager, &name, &value, result)?;
+    }
+    Ok(())
+}
+
+/// Выводит результат записи. Ошибка канала связи возвращается, так как
+/// после неё канал нужно переподключить
+fn report_write(
+    manager: &ModbusManager,
+    name: &str,
+    value: &str,
+    result: Result<(), TaskError>,
+) -> Result<(), TaskError> {
+    match result {
+        Ok(()) => println!("Запись {name} = {value} подтверждена устройством"),
+        Err(err) if err.error.breaks_connection(manager.protocol()) => return Err(err),
+        Err(err) => println!("Ошибка записи {name}: {err}"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::simulator_config::SimulatorConfig;
+    use crate::simulator::Simulator;
+    use crate::task::{CommandType, Task};
+    use std::net::{TcpListener, TcpStream};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn write_wakes_idle_channel() -> Result<(), Box<dyn std::error::Error>> {
+        let simulator = Simulator::new(&SimulatorConfig::default())?;
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        std::thread::spawn(move || simulator.serve(listener));
+        let mut stream = TcpStream::connect(addr)?;
+        let item: ConfigItem = serde_yaml::from_str(
+            "{storage: ao, id: 1, unit_id: 1, name: setpoint, start: 5, poll_interval: 60}",
+        )?;
+        let mut manager = ModbusManager::new(&[item], ProtocolType::Tcp, 0)?;
+        manager.poll(&mut stream)?;
+        let (sender, writes) = channel();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            sender.send(("setpoint".to_string(), "42".to_string()))
+        });
+        // Запись выполняется задолго до следующего опроса через минуту
+        let started = Instant::now();
+        let woken = manager.wait(&writes);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        write_requests(
+            &mut stream,
+            &manager,
+            woken.into_iter().chain(writes.try_iter()),
+        )?;
+        let mut read = Task::new(
+            1,
+            1,
+            ProtocolType::Tcp,
+            CommandType::ReadHoldingRegisters,
+            5,
+            1,
+            vec![],
+        );
+        assert_eq!(
+            modbus_manager::exchange(&mut stream, &mut read)?,
+            Some(vec![42])
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,46 @@
+use std::io::{Cursor, Read, Write};
+
+/// Канал связи для тестов, возвращающий заранее подготовленные ответы
+pub struct MockStream {
+    pub input: Cursor<Vec<u8>>,
+    pub output: Vec<u8>,
+    /// После подготовленных ответов устройства молчат: чтение завершается
+    /// таймаутом, а не концом потока
+    silent: bool,
+}
+
+impl MockStream {
+    pub fn new(input: Vec<u8>) -> Self {
+        Self {
+            input: Cursor::new(input),
+            output: vec![],
+            silent: false,
+        }
+    }
+
+    /// Линия, на которой после подготовленных ответов устройства молчат
+    pub fn silent(input: Vec<u8>) -> Self {
+        Self {
+            silent: true,
+            ..Self::new(input)
+        }
+    }
+}
+
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.input.read(buf)? {
+            0 if self.silent && !buf.is_empty() => Err(std::io::ErrorKind::TimedOut.into()),
+            size => Ok(size),
+        }
+    }
+}
+
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
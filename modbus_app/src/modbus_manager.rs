@@ -1,56 +1,983 @@
-use rmodbus::{client::ModbusRequest, guess_response_frame_len, ModbusProto};
+use getset::Getters;
+use rmodbus::ErrorKind;
 use std::io::{Read, Write};
-use std::net::TcpStream;
-use std::time::Duration;
-
-//pub use crate::Config::try_read_config_file;
-
-pub fn client() {
-    let timeout = Duration::from_secs(1);
-    //Подключение к локальному хосту
-    let mut stream = TcpStream::connect("127.0.0.1").unwrap();
-    stream.set_read_timeout(Some(timeout)).unwrap();
-    stream.set_write_timeout(Some(timeout)).unwrap();
-    //Содаем пустой запрос modbus
-    let mut mreq = ModbusRequest::new(1, ModbusProto::TcpUdp);
-    mreq.tr_id = 2;
-    //Создание адреса 8, 9 и запись в request
-    let mut request = Vec::new();
-    mreq.generate_set_coils_bulk(8, &[true, true], &mut request)
-        .unwrap();
-    //Отправка request
-    stream.write(&request).unwrap();
-    //Создание массива из первых шести элементов равных нулю типа u8
-    let mut buf = [0u8; 6];
-    stream.read_exact(&mut buf).unwrap();
-    //Формирует буфер ответа
-    let mut response = Vec::new();
-    response.extend_from_slice(&buf);
-    let len = guess_response_frame_len(&buf, ModbusProto::TcpUdp).unwrap();
-    if len > 6 {
-        let mut rest = vec![0u8; (len - 6) as usize];
-        stream.read_exact(&mut rest).unwrap();
-        response.extend(rest);
-    }
-
-    mreq.parse_ok(&response).unwrap();
-
-    mreq.generate_get_coils(0, 5, &mut request).unwrap();
-    stream.write(&request).unwrap();
-    let mut buf = [0u8; 6];
-    stream.read_exact(&mut buf).unwrap();
-    let mut response = Vec::new();
-    response.extend_from_slice(&buf);
-    let len = guess_response_frame_len(&buf, ModbusProto::TcpUdp).unwrap();
-    if len > 6 {
-        let mut rest = vec![0u8; (len - 6) as usize];
-        stream.read_exact(&mut rest).unwrap();
-        response.extend(rest);
-    }
-
-    let mut data = Vec::new();
-    mreq.parse_bool(&response, &mut data).unwrap();
-    for i in 0..data.len() {
-        println!("{} {}", i, data[i]);
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Instant;
+
+use crate::config_manager::modbus_variables::{ConfigItem, ModbusRequestItems};
+use crate::decoder::{decode_item, Value};
+use crate::error::{ExceptionCode, ModbusError, TaskContext, TaskError};
+use crate::planner::{plan, PollBlock};
+use crate::scheduler::Scheduler;
+use crate::task::{ProtocolType, Task};
+use crate::transaction::TransactionManager;
+
+/// Данные, прочитанные запросом блока, или ошибка запроса
+type BlockResult = Result<Option<Vec<u16>>, TaskError>;
+
+/// Переменная, опрашиваемая менеджером, и её последнее прочитанное значение
+#[derive(Getters)]
+#[get = "pub"]
+pub struct PollVariable {
+    item: ModbusRequestItems,
+    value: Option<Value>,
+    /// Ошибка последнего опроса переменной
+    error: Option<String>,
+    #[getset(skip)]
+    updated: bool,
+}
+
+/// Циклический опрос переменных из конфигурации
+pub struct ModbusManager {
+    protocol: ProtocolType,
+    variables: Vec<PollVariable>,
+    blocks: Vec<PollBlock>,
+    scheduler: Scheduler,
+    transactions: TransactionManager,
+    /// Наибольшее количество запросов, ожидающих ответа одновременно
+    window: usize,
+}
+
+impl ModbusManager {
+    pub fn new(
+        items: &[ConfigItem],
+        protocol: ProtocolType,
+        max_gap: u16,
+    ) -> Result<Self, ModbusError> {
+        let request_items = items
+            .iter()
+            .map(|item| ModbusRequestItems::try_from(item.to_owned()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let blocks = plan(&request_items, protocol.to_owned(), max_gap);
+        let scheduler = Scheduler::new(blocks.iter().map(|block| block.interval), Instant::now());
+        let variables = request_items
+            .into_iter()
+            .map(|item| PollVariable {
+                item,
+                value: None,
+                error: None,
+                updated: false,
+            })
+            .collect();
+        Ok(Self {
+            protocol,
+            variables,
+            blocks,
+            scheduler,
+            transactions: TransactionManager::default(),
+            window: 1,
+        })
+    }
+
+    /// Разрешает отправлять до window запросов, не дожидаясь ответов.
+    /// Имеет смысл только для Modbus TCP, где ответы различаются номером
+    /// транзакции.
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    /// Опрашивает блоки, срок опроса которых наступил.
+    ///
+    /// Каждый блок опрашивается за вызов не более одного раза. Исключение
+    /// modbus или ошибка разбора ответа по отдельному запросу не прерывают
+    /// опрос, значения переменных этого запроса при этом сбрасываются.
+    /// Ошибка канала связи прерывает опрос, так как канал больше не пригоден
+    /// для обмена.
+    pub fn poll<S: Read + Write>(&mut self, stream: &mut S) -> Result<(), TaskError> {
+        let mut polled = vec![false; self.blocks.len()];
+        loop {
+            let batch = self.next_batch(&mut polled);
+            let mut results = vec![];
+            let outcome = match batch.as_slice() {
+                [] => break,
+                &[index] => {
+                    let result = exchange(stream, &mut self.blocks[index].task);
+                    self.transactions.clear();
+                    results.push((index, result));
+                    Ok(())
+                }
+                _ => self.exchange_batch(stream, &batch, &mut results),
+            };
+            // Ответы, принятые до ошибки канала связи, не теряются
+            for (index, result) in results {
+                self.complete_block(index, result)?;
+            }
+            outcome?;
+        }
+        Ok(())
+    }
+
+    /// Асинхронный вариант poll, ожидание каждого ответа ограничено timeout
+    #[cfg(feature = "async")]
+    pub async fn poll_async<S>(
+        &mut self,
+        stream: &mut S,
+        timeout: std::time::Duration,
+    ) -> Result<(), TaskError>
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        let mut polled = vec![false; self.blocks.len()];
+        loop {
+            let batch = self.next_batch(&mut polled);
+            let mut results = vec![];
+            let outcome = match batch.as_slice() {
+                [] => break,
+                &[index] => {
+                    let result =
+                        exchange_async(stream, &mut self.blocks[index].task, timeout).await;
+                    self.transactions.clear();
+                    results.push((index, result));
+                    Ok(())
+                }
+                _ => {
+                    self.exchange_batch_async(stream, &batch, timeout, &mut results)
+                        .await
+                }
+            };
+            for (index, result) in results {
+                self.complete_block(index, result)?;
+            }
+            outcome?;
+        }
+        Ok(())
+    }
+
+    /// Блоки, срок опроса которых наступил и которые ещё не опрашивались за
+    /// вызов, не более размера окна. Каждому блоку выдается номер транзакции.
+    fn next_batch(&mut self, polled: &mut [bool]) -> Vec<usize> {
+        let batch = self
+            .scheduler
+            .due(Instant::now())
+            .into_iter()
+            .filter(|&index| !polled[index])
+            .take(self.window)
+            .collect::<Vec<_>>();
+        for &index in &batch {
+            polled[index] = true;
+            let id = self.transactions.allocate(index);
+            self.blocks[index].task.set_id(id);
+        }
+        batch
+    }
+
+    /// Формирует запросы пакета. Блоки, запрос которых сформировать не
+    /// удалось, сразу получают ошибку и не ожидают ответа.
+    fn prepare_batch(&mut self, batch: &[usize]) -> (Vec<u8>, Vec<(usize, BlockResult)>) {
+        let mut requests = vec![];
+        let mut results = vec![];
+        for &index in batch {
+            let task = &mut self.blocks[index].task;
+            match task.generate_request() {
+                Ok(request) => requests.extend(request),
+                Err(err) => {
+                    self.transactions.resolve(task.id());
+                    results.push((
+                        index,
+                        Err(TaskError::new(task.context(), ModbusError::request(err))),
+                    ));
+                }
+            }
+        }
+        (requests, results)
+    }
+
+    /// Находит блок по номеру транзакции из заголовка ответа Modbus TCP
+    fn match_response(&mut self, head: &[u8], context: &TaskContext) -> Result<usize, TaskError> {
+        let id = u16::from_be_bytes([head[0], head[1]]);
+        self.transactions.resolve(id).ok_or_else(|| {
+            self.transactions.clear();
+            TaskError::new(
+                context.to_owned(),
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("ответ на неизвестную транзакцию {id}"),
+                ),
+            )
+        })
+    }
+
+    /// Длина ответа на запрос блока. Если длину определить нельзя, дальнейшие
+    /// ответы в потоке не разобрать, поэтому ошибка считается ошибкой канала.
+    fn response_len(&mut self, index: usize, head: &[u8]) -> Result<usize, TaskError> {
+        let task = &self.blocks[index].task;
+        task.get_responce_len(head).map_err(|err| {
+            self.transactions.clear();
+            TaskError::new(
+                task.context(),
+                std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()),
+            )
+        })
+    }
+
+    /// Отправляет запросы пакета подряд и сопоставляет ответы по номеру
+    /// транзакции. Ответы добавляются в results по мере приема, поэтому при
+    /// ошибке канала связи там остаются ответы, принятые до неё
+    fn exchange_batch<S: Read + Write>(
+        &mut self,
+        stream: &mut S,
+        batch: &[usize],
+        results: &mut Vec<(usize, BlockResult)>,
+    ) -> Result<(), TaskError> {
+        let context = self.blocks[batch[0]].task.context();
+        let (requests, prepared) = self.prepare_batch(batch);
+        results.extend(prepared);
+        let io = |err: std::io::Error| TaskError::new(context.to_owned(), err);
+        stream
+            .write_all(&requests)
+            .and_then(|_| stream.flush())
+            .map_err(io)?;
+        while self.transactions.is_pending() {
+            let mut head = vec![0u8; ProtocolType::Tcp.head_len()];
+            stream.read_exact(&mut head).map_err(io)?;
+            let index = self.match_response(&head, &context)?;
+            let mut tail = vec![0u8; self.response_len(index, &head)?.saturating_sub(head.len())];
+            stream.read_exact(&mut tail).map_err(io)?;
+            results.push((
+                index,
+                parse_response(&self.blocks[index].task, &head, &tail),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Асинхронный вариант exchange_batch
+    #[cfg(feature = "async")]
+    async fn exchange_batch_async<S>(
+        &mut self,
+        stream: &mut S,
+        batch: &[usize],
+        timeout: std::time::Duration,
+        results: &mut Vec<(usize, BlockResult)>,
+    ) -> Result<(), TaskError>
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let context = self.blocks[batch[0]].task.context();
+        let (requests, prepared) = self.prepare_batch(batch);
+        results.extend(prepared);
+        let io = |result: Result<std::io::Result<()>, tokio::time::error::Elapsed>| match result {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(err)) => Err(TaskError::new(context.to_owned(), err)),
+            Err(_) => Err(TaskError::new(context.to_owned(), ModbusError::Timeout)),
+        };
+        io(tokio::time::timeout(timeout, async {
+            stream.write_all(&requests).await?;
+            stream.flush().await
+        })
+        .await)?;
+        while self.transactions.is_pending() {
+            let mut head = vec![0u8; ProtocolType::Tcp.head_len()];
+            io(tokio::time::timeout(timeout, stream.read_exact(&mut head))
+                .await
+                .map(|result| result.map(|_| ())))?;
+            let index = self.match_response(&head, &context)?;
+            let mut tail = vec![0u8; self.response_len(index, &head)?.saturating_sub(head.len())];
+            io(tokio::time::timeout(timeout, stream.read_exact(&mut tail))
+                .await
+                .map(|result| result.map(|_| ())))?;
+            results.push((
+                index,
+                parse_response(&self.blocks[index].task, &head, &tail),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Раскладывает ответ на запрос блока по переменным и назначает срок
+    /// следующего опроса
+    fn complete_block(&mut self, index: usize, result: BlockResult) -> Result<(), TaskError> {
+        let block = &self.blocks[index];
+        match result {
+            Ok(data) => {
+                let data = data.unwrap_or_default();
+                for (index, registers) in block.scatter(&data) {
+                    let variable = &mut self.variables[index];
+                    variable.updated = true;
+                    variable.value = match registers {
+                        Some(registers) => decode_item(&variable.item, &registers),
+                        None => None,
+                    };
+                    variable.error = None;
+                    if variable.value.is_none() {
+                        let err = TaskError::new(
+                            block.task.context(),
+                            ModbusError::Decode(format!(
+                                "ответ не содержит значения типа {:?}",
+                                variable.item.data_type
+                            )),
+                        );
+                        println!("Ошибка чтения переменной {}: {err}", variable.item.name);
+                        variable.error = Some(err.to_string());
+                    }
+                }
+            }
+            Err(err) if err.error.breaks_connection(block.task.protocol()) => return Err(err),
+            Err(err) => {
+                for variable in &block.variables {
+                    let variable = &mut self.variables[variable.index];
+                    println!("Ошибка чтения переменной {}: {err}", variable.item.name);
+                    variable.updated = true;
+                    variable.value = None;
+                    variable.error = Some(err.to_string());
+                }
+            }
+        }
+        if let Some(late) = self.scheduler.complete(index, Instant::now()) {
+            println!(
+                "Опрос не успевает за интервалом {:?}: запрос ({}) выполнен с опозданием {late:?}",
+                block.interval,
+                block.task.context()
+            );
+        }
+        Ok(())
+    }
+
+    /// Начинает опрос всех блоков заново, не считая время без связи опозданием
+    pub fn restart(&mut self) {
+        self.transactions.clear();
+        self.scheduler.restart(Instant::now());
+    }
+
+    /// Ожидает наступления срока опроса ближайшего блока или запроса из
+    /// writes, например записи по команде. Поступивший запрос возвращается,
+    /// чтобы его можно было выполнить, не дожидаясь очередного опроса
+    pub fn wait<T>(&self, writes: &Receiver<T>) -> Option<T> {
+        let deadline = self.scheduler.next_deadline()?;
+        match writes.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(request) => Some(request),
+            Err(RecvTimeoutError::Timeout) => None,
+            // Запросов больше не будет, остается дождаться срока опроса
+            Err(RecvTimeoutError::Disconnected) => {
+                std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                None
+            }
+        }
+    }
+
+    /// Асинхронный вариант wait
+    #[cfg(feature = "async")]
+    pub async fn wait_async<T>(
+        &self,
+        writes: &mut tokio::sync::mpsc::UnboundedReceiver<T>,
+    ) -> Option<T> {
+        let deadline = self.scheduler.next_deadline()?;
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline.into()) => None,
+            Some(request) = writes.recv() => Some(request),
+        }
+    }
+
+    /// Переменные, обновленные с момента прошлого вызова
+    pub fn take_updated(&mut self) -> Vec<&PollVariable> {
+        self.variables
+            .iter_mut()
+            .filter(|variable| variable.updated)
+            .map(|variable| {
+                variable.updated = false;
+                &*variable
+            })
+            .collect()
+    }
+
+    /// Опрашиваемая переменная по имени
+    pub fn variable(&self, name: &str) -> Option<&PollVariable> {
+        self.variables
+            .iter()
+            .find(|variable| variable.item.name == name)
+    }
+
+    pub fn protocol(&self) -> &ProtocolType {
+        &self.protocol
+    }
+}
+
+/// Отправляет запрос задачи в канал связи и разбирает ответ
+pub fn exchange<S: Read + Write>(
+    stream: &mut S,
+    task: &mut Task,
+) -> Result<Option<Vec<u16>>, TaskError> {
+    let context = task.context();
+    let error = |err: ModbusError| TaskError::new(context.to_owned(), err);
+    let request = task
+        .generate_request()
+        .map_err(|err| error(ModbusError::request(err)))?;
+    stream
+        .write_all(&request)
+        .and_then(|_| stream.flush())
+        .map_err(|err| error(err.into()))?;
+    let mut frame = vec![0u8; task.protocol().head_len()];
+    stream
+        .read_exact(&mut frame)
+        .map_err(|err| error(err.into()))?;
+    loop {
+        let missing = missing_len(task, &frame).map_err(|err| error(err.into()))?;
+        if missing == 0 {
+            break;
+        }
+        let received = frame.len();
+        frame.resize(received + missing, 0);
+        stream
+            .read_exact(&mut frame[received..])
+            .map_err(|err| error(err.into()))?;
+    }
+    parse_response(task, &frame, &[])
+}
+
+/// Количество байт, недостающих до конца ответа на запрос задачи, 0 - ответ
+/// принят полностью. Длина некоторых ответов уточняется по мере приема,
+/// поэтому exchange и exchange_async дочитывают кадр, пока значение не
+/// станет нулевым
+fn missing_len(task: &Task, frame: &[u8]) -> Result<usize, ErrorKind> {
+    Ok(task.get_responce_len(frame)?.saturating_sub(frame.len()))
+}
+
+/// Асинхронный вариант exchange, весь обмен должен уложиться в timeout
+#[cfg(feature = "async")]
+pub async fn exchange_async<S>(
+    stream: &mut S,
+    task: &mut Task,
+    timeout: std::time::Duration,
+) -> Result<Option<Vec<u16>>, TaskError>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let context = task.context();
+    let error = |err: ModbusError| TaskError::new(context.to_owned(), err);
+    let request = task
+        .generate_request()
+        .map_err(|err| error(ModbusError::request(err)))?;
+    let exchange = async {
+        stream.write_all(&request).await?;
+        stream.flush().await?;
+        let mut frame = vec![0u8; task.protocol().head_len()];
+        stream.read_exact(&mut frame).await?;
+        loop {
+            let missing = match missing_len(task, &frame) {
+                Ok(missing) => missing,
+                Err(err) => return Ok(Err(err)),
+            };
+            if missing == 0 {
+                break;
+            }
+            let received = frame.len();
+            frame.resize(received + missing, 0);
+            stream.read_exact(&mut frame[received..]).await?;
+        }
+        Ok(Ok(frame))
+    };
+    let frame = tokio::time::timeout(timeout, exchange)
+        .await
+        .map_err(|_| error(ModbusError::Timeout))?
+        .map_err(|err: std::io::Error| error(err.into()))?
+        .map_err(|err| error(err.into()))?;
+    parse_response(task, &frame, &[])
+}
+
+/// Разбирает ответ на запрос задачи
+fn parse_response(task: &Task, head: &[u8], tail: &[u8]) -> Result<Option<Vec<u16>>, TaskError> {
+    task.show_result(head, tail).map_err(|err| {
+        let frame = [head, tail].concat();
+        let function = task.protocol().frame_start() + 1;
+        let err = match (err, frame.get(function), frame.get(function + 1)) {
+            (ErrorKind::FrameCRCError | ErrorKind::FrameBroken, _, _) => err.into(),
+            // rmodbus теряет часть кодов исключений, поэтому код берется из кадра
+            (_, Some(function), Some(&code)) if function & 0x80 != 0 => {
+                ModbusError::Exception(ExceptionCode(code))
+            }
+            (err, _, _) => err.into(),
+        };
+        TaskError::new(task.context(), err)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::modbus_variables::{ByteOrder, DataType, ModbusStorage};
+    use crate::mock::MockStream;
+    use crate::task::CommandType;
+
+    fn config_item(storage: ModbusStorage, id: u16, name: &str, start: u16) -> ConfigItem {
+        ConfigItem {
+            storage: Some(storage),
+            id,
+            unit_id: 1,
+            name: name.to_string(),
+            start: Some(start),
+            address: None,
+            data_type: None,
+            length: None,
+            byte_order: None,
+            scale: None,
+            offset: None,
+            raw_range: None,
+            eng_range: None,
+            unit: None,
+            precision: None,
+            bit: None,
+            mask: None,
+            poll_interval: None,
+            group: None,
+            channel: None,
+        }
+    }
+
+    #[test]
+    fn poll_reads_all_variables() -> Result<(), TaskError> {
+        let items = vec![
+            config_item(ModbusStorage::AO, 1, "setpoint", 10),
+            config_item(ModbusStorage::DO, 2, "pump", 3),
+        ];
+        let mut manager = ModbusManager::new(&items, ProtocolType::Tcp, 0).unwrap();
+        let mut responses = vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x01, 0x01, 0x01, 0x01];
+        responses.extend([
+            0x00, 0x01, 0x00, 0x00, 0x00, 0x05, 0x01, 0x03, 0x02, 0x01, 0x2C,
+        ]);
+        let mut stream = MockStream::new(responses);
+        manager.poll(&mut stream)?;
+        assert_eq!(
+            &stream.output,
+            &[
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x01, 0x01, 0x00, 0x03, 0x00, 0x01, 0x00, 0x01,
+                0x00, 0x00, 0x00, 0x06, 0x01, 0x03, 0x00, 0x0A, 0x00, 0x01
+            ]
+        );
+        assert_eq!(manager.variables[0].value(), &Some(Value::U16(300)));
+        assert_eq!(manager.variables[1].value(), &Some(Value::Bool(true)));
+        Ok(())
+    }
+
+    #[test]
+    fn poll_keeps_going_after_modbus_exception() -> Result<(), TaskError> {
+        let items = vec![
+            config_item(ModbusStorage::AI, 1, "temperature", 100),
+            config_item(ModbusStorage::AO, 2, "pressure", 101),
+        ];
+        let mut manager = ModbusManager::new(&items, ProtocolType::Tcp, 0).unwrap();
+        let mut responses = vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x01, 0x84, 0x02];
+        responses.extend([
+            0x00, 0x01, 0x00, 0x00, 0x00, 0x05, 0x01, 0x03, 0x02, 0x00, 0x07,
+        ]);
+        let mut stream = MockStream::new(responses);
+        manager.poll(&mut stream)?;
+        assert_eq!(manager.variables[0].value(), &None);
+        assert_eq!(manager.variables[1].value(), &Some(Value::U16(7)));
+        Ok(())
+    }
+
+    #[test]
+    fn exchange_reports_exception_with_context() {
+        let mut stream = MockStream::new(vec![
+            0x00, 0x07, 0x00, 0x00, 0x00, 0x03, 0x05, 0x83, 0x0B, 0x00, 0x08, 0x00, 0x00, 0x00,
+            0x03, 0x05, 0x83, 0x06,
+        ]);
+        let mut task = Task::new(
+            7,
+            5,
+            ProtocolType::Tcp,
+            CommandType::ReadHoldingRegisters,
+            40,
+            2,
+            vec![],
+        );
+        let err = exchange(&mut stream, &mut task).unwrap_err();
+        assert!(matches!(
+            err.error,
+            ModbusError::Exception(ExceptionCode(0x0B))
+        ));
+        assert_eq!(
+            err.context,
+            TaskContext {
+                unit_id: 5,
+                function: 0x03,
+                start: 40,
+                count: 2
+            }
+        );
+        let mut task = Task::new(
+            8,
+            5,
+            ProtocolType::Tcp,
+            CommandType::ReadHoldingRegisters,
+            40,
+            2,
+            vec![],
+        );
+        let err = exchange(&mut stream, &mut task).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "исключение modbus 06 SlaveDeviceBusy (устройство 5, функция 03, адрес 40, количество 2)"
+        );
+    }
+
+    #[test]
+    fn exchange_reports_request_errors_as_config() {
+        let mut stream = MockStream::new(vec![]);
+        let mut task = Task::new(
+            1,
+            1,
+            ProtocolType::Tcp,
+            CommandType::PresetMultipleRegisters,
+            0,
+            0,
+            vec![],
+        );
+        let err = exchange(&mut stream, &mut task).unwrap_err();
+        // Запрос не отправлен, исключения устройства не было
+        assert!(matches!(err.error, ModbusError::Config(_)));
+        assert!(stream.output.is_empty());
+    }
+
+    #[test]
+    fn poll_reads_adjacent_variables_in_one_request() -> Result<(), TaskError> {
+        let items = vec![
+            config_item(ModbusStorage::AO, 1, "low", 10),
+            config_item(ModbusStorage::AO, 2, "high", 11),
+        ];
+        let mut manager = ModbusManager::new(&items, ProtocolType::Tcp, 0).unwrap();
+        let mut stream = MockStream::new(vec![
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x01, 0x03, 0x04, 0x00, 0x0A, 0x00, 0x14,
+        ]);
+        manager.poll(&mut stream)?;
+        assert_eq!(
+            &stream.output,
+            &[0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x01, 0x03, 0x00, 0x0A, 0x00, 0x02]
+        );
+        assert_eq!(manager.variables[0].value(), &Some(Value::U16(10)));
+        assert_eq!(manager.variables[1].value(), &Some(Value::U16(20)));
+        Ok(())
+    }
+
+    #[test]
+    fn poll_decodes_typed_values() -> Result<(), TaskError> {
+        let items = vec![
+            ConfigItem {
+                data_type: Some(DataType::F32),
+                byte_order: Some(ByteOrder::Cdab),
+                ..config_item(ModbusStorage::AI, 1, "power", 0)
+            },
+            ConfigItem {
+                data_type: Some(DataType::String),
+                length: Some(2),
+                ..config_item(ModbusStorage::AI, 2, "model", 2)
+            },
+        ];
+        let mut manager = ModbusManager::new(&items, ProtocolType::Tcp, 0).unwrap();
+        let mut stream = MockStream::new(vec![
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x0B, 0x01, 0x04, 0x08, 0x00, 0x00, 0x42, 0x48, 0x50,
+            0x4D, 0x33, 0x00,
+        ]);
+        manager.poll(&mut stream)?;
+        assert_eq!(manager.variables[0].value(), &Some(Value::F32(50.0)));
+        assert_eq!(
+            manager.variables[1].value(),
+            &Some(Value::String("PM3".to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn poll_applies_scaling() -> Result<(), TaskError> {
+        let items = vec![
+            ConfigItem {
+                raw_range: Some([0.0, 27648.0]),
+                eng_range: Some([0.0, 100.0]),
+                unit: Some("%".to_string()),
+                ..config_item(ModbusStorage::AI, 1, "level", 0)
+            },
+            ConfigItem {
+                scale: Some(0.1),
+                ..config_item(ModbusStorage::AI, 2, "temperature", 1)
+            },
+        ];
+        let mut manager = ModbusManager::new(&items, ProtocolType::Tcp, 0).unwrap();
+        let mut stream = MockStream::new(vec![
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x01, 0x04, 0x04, 0x6C, 0x00, 0x00, 0xFA,
+        ]);
+        manager.poll(&mut stream)?;
+        assert_eq!(manager.variables[0].value(), &Some(Value::F64(100.0)));
+        assert_eq!(manager.variables[1].value(), &Some(Value::F64(25.0)));
+        Ok(())
+    }
+
+    #[test]
+    fn poll_reads_bits_of_shared_register() -> Result<(), TaskError> {
+        let items = vec![
+            ConfigItem {
+                bit: Some(0),
+                ..config_item(ModbusStorage::AO, 1, "running", 5)
+            },
+            ConfigItem {
+                bit: Some(9),
+                ..config_item(ModbusStorage::AO, 2, "fault", 5)
+            },
+            ConfigItem {
+                mask: Some(0x00F0),
+                ..config_item(ModbusStorage::AO, 3, "warnings", 5)
+            },
+        ];
+        let mut manager = ModbusManager::new(&items, ProtocolType::Tcp, 0).unwrap();
+        let mut stream = MockStream::new(vec![
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x01, 0x03, 0x02, 0x02, 0x01,
+        ]);
+        manager.poll(&mut stream)?;
+        assert_eq!(
+            &stream.output,
+            &[0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x01, 0x03, 0x00, 0x05, 0x00, 0x01]
+        );
+        assert_eq!(manager.variables[0].value(), &Some(Value::Bool(true)));
+        assert_eq!(manager.variables[1].value(), &Some(Value::Bool(true)));
+        assert_eq!(manager.variables[2].value(), &Some(Value::Bool(false)));
+        Ok(())
+    }
+
+    #[test]
+    fn poll_follows_intervals() -> Result<(), TaskError> {
+        let items = vec![
+            ConfigItem {
+                poll_interval: Some(60.0),
+                ..config_item(ModbusStorage::AI, 1, "counter", 0)
+            },
+            ConfigItem {
+                poll_interval: Some(0.05),
+                ..config_item(ModbusStorage::AI, 2, "alarm", 10)
+            },
+        ];
+        let mut manager = ModbusManager::new(&items, ProtocolType::Tcp, 100).unwrap();
+        let mut responses = vec![
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x01, 0x04, 0x02, 0x00, 0x01,
+        ];
+        responses.extend([
+            0x00, 0x01, 0x00, 0x00, 0x00, 0x05, 0x01, 0x04, 0x02, 0x00, 0x07,
+        ]);
+        responses.extend([
+            0x00, 0x02, 0x00, 0x00, 0x00, 0x05, 0x01, 0x04, 0x02, 0x00, 0x00,
+        ]);
+        let mut stream = MockStream::new(responses);
+        manager.poll(&mut stream)?;
+        assert_eq!(manager.variables[0].value(), &Some(Value::U16(7)));
+        assert_eq!(manager.variables[1].value(), &Some(Value::U16(1)));
+        manager.poll(&mut stream)?;
+        assert_eq!(stream.output.len(), 24);
+        // Без источников запросов записи ожидается только срок опроса
+        let (_, writes) = std::sync::mpsc::channel::<()>();
+        assert_eq!(manager.wait(&writes), None);
+        manager.poll(&mut stream)?;
+        assert_eq!(stream.output.len(), 36);
+        assert_eq!(manager.variables[1].value(), &Some(Value::U16(0)));
+        Ok(())
+    }
+
+    /// Дочитывание ответа, общее для exchange и exchange_async, проверяется
+    /// и без функции async
+    #[test]
+    fn response_is_read_until_complete() -> Result<(), ErrorKind> {
+        let task = Task::new(
+            1,
+            1,
+            ProtocolType::Uart,
+            CommandType::ReadDeviceIdentification {
+                code: 1,
+                object_id: 0,
+            },
+            0,
+            0,
+            vec![],
+        );
+        let frame = ProtocolType::Uart.encode_frame(
+            0,
+            &[
+                0x01, 0x2B, 0x0E, 0x01, 0x01, 0x00, 0x00, 0x02, 0x00, 0x02, b'A', b'B', 0x01, 0x01,
+                b'C',
+            ],
+        );
+        let mut received = task.protocol().head_len();
+        let mut reads = vec![];
+        loop {
+            let missing = missing_len(&task, &frame[..received])?;
+            if missing == 0 {
+                break;
+            }
+            reads.push(missing);
+            received += missing;
+        }
+        assert_eq!(received, frame.len());
+        assert!(reads.len() > 1, "{reads:?}");
+        let mut stream =
+            MockStream::new(ProtocolType::Tcp.encode_frame(1, &[0x01, 0x03, 0x02, 0x00, 0x07]));
+        let mut task = Task::new(
+            1,
+            1,
+            ProtocolType::Tcp,
+            CommandType::ReadHoldingRegisters,
+            0,
+            1,
+            vec![],
+        );
+        assert_eq!(
+            missing_len(&task, &[0x00, 0x01, 0x00, 0x00, 0x00, 0x05, 0x01])?,
+            4
+        );
+        assert_eq!(exchange(&mut stream, &mut task).ok(), Some(Some(vec![7])));
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn wait_async_wakes_on_write() -> Result<(), Box<dyn std::error::Error>> {
+        let item = ConfigItem {
+            poll_interval: Some(60.0),
+            ..config_item(ModbusStorage::AO, 1, "setpoint", 0)
+        };
+        let mut manager = ModbusManager::new(&[item], ProtocolType::Tcp, 0)?;
+        let mut stream =
+            MockStream::new(ProtocolType::Tcp.encode_frame(0, &[0x01, 0x03, 0x02, 0x00, 0x07]));
+        manager.poll(&mut stream)?;
+        let (sender, mut writes) = tokio::sync::mpsc::unbounded_channel();
+        sender.send("setpoint")?;
+        let woken = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            manager.wait_async(&mut writes),
+        )
+        .await?;
+        assert_eq!(woken, Some("setpoint"));
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn exchange_async_reads_and_times_out() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::config_manager::simulator_config::SimulatorConfig;
+        use crate::simulator::Simulator;
+        use std::time::Duration;
+
+        let simulator = Simulator::new(&SimulatorConfig::default())?;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        std::thread::spawn(move || simulator.serve(listener));
+        let mut stream = tokio::net::TcpStream::connect(addr).await?;
+        let timeout = Duration::from_secs(1);
+        let mut write = Task::new(
+            1,
+            1,
+            ProtocolType::Tcp,
+            CommandType::PresetSingleRegister,
+            5,
+            1,
+            vec![7],
+        );
+        assert_eq!(
+            exchange_async(&mut stream, &mut write, timeout).await?,
+            None
+        );
+        let mut read = Task::new(
+            2,
+            1,
+            ProtocolType::Tcp,
+            CommandType::ReadHoldingRegisters,
+            5,
+            1,
+            vec![],
+        );
+        assert_eq!(
+            exchange_async(&mut stream, &mut read, timeout).await?,
+            Some(vec![7])
+        );
+        // Соединение принято, но устройство не отвечает
+        let silent = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let mut stream = tokio::net::TcpStream::connect(silent.local_addr()?).await?;
+        let err = exchange_async(&mut stream, &mut read, Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error, ModbusError::Timeout));
+        Ok(())
+    }
+
+    #[test]
+    fn poll_pipelines_requests_within_window() -> Result<(), TaskError> {
+        let items = vec![
+            config_item(ModbusStorage::AO, 1, "first", 0),
+            config_item(ModbusStorage::AO, 2, "second", 10),
+            config_item(ModbusStorage::AO, 3, "third", 20),
+        ];
+        let mut manager = ModbusManager::new(&items, ProtocolType::Tcp, 0)
+            .unwrap()
+            .with_window(2);
+        // Ответы приходят не в порядке запросов
+        let mut responses = vec![
+            0x00, 0x01, 0x00, 0x00, 0x00, 0x05, 0x01, 0x03, 0x02, 0x00, 0x02,
+        ];
+        responses.extend([0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x01, 0x83, 0x06]);
+        responses.extend([
+            0x00, 0x02, 0x00, 0x00, 0x00, 0x05, 0x01, 0x03, 0x02, 0x00, 0x03,
+        ]);
+        let mut stream = MockStream::new(responses);
+        manager.poll(&mut stream)?;
+        let ids = stream
+            .output
+            .chunks(12)
+            .map(|request| (request[1], request[9]))
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![(0, 0), (1, 10), (2, 20)]);
+        assert_eq!(manager.variables[0].value(), &None);
+        assert_eq!(manager.variables[1].value(), &Some(Value::U16(2)));
+        assert_eq!(manager.variables[2].value(), &Some(Value::U16(3)));
+        Ok(())
+    }
+
+    #[test]
+    fn poll_keeps_responses_received_before_connection_error() {
+        let items = vec![
+            config_item(ModbusStorage::AO, 1, "first", 0),
+            config_item(ModbusStorage::AO, 2, "second", 10),
+        ];
+        let mut manager = ModbusManager::new(&items, ProtocolType::Tcp, 0)
+            .unwrap()
+            .with_window(2);
+        // Ответ на второй запрос принят, затем соединение закрылось
+        let mut stream = MockStream::new(vec![
+            0x00, 0x01, 0x00, 0x00, 0x00, 0x05, 0x01, 0x03, 0x02, 0x00, 0x02,
+        ]);
+        let err = manager.poll(&mut stream).unwrap_err();
+        assert!(err.error.breaks_connection(&ProtocolType::Tcp));
+        assert_eq!(manager.variables[0].value(), &None);
+        assert_eq!(manager.variables[1].value(), &Some(Value::U16(2)));
+        let updated = manager
+            .take_updated()
+            .into_iter()
+            .map(|variable| variable.item().name.to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(updated, vec!["second".to_string()]);
+    }
+
+    #[test]
+    fn poll_rejects_unknown_transaction() {
+        let items = vec![
+            config_item(ModbusStorage::AO, 1, "first", 0),
+            config_item(ModbusStorage::AO, 2, "second", 10),
+        ];
+        let mut manager = ModbusManager::new(&items, ProtocolType::Tcp, 0)
+            .unwrap()
+            .with_window(2);
+        let mut stream = MockStream::new(vec![
+            0x00, 0x07, 0x00, 0x00, 0x00, 0x05, 0x01, 0x03, 0x02, 0x00, 0x02,
+        ]);
+        let err = manager.poll(&mut stream).unwrap_err();
+        assert!(err.error.breaks_connection(&ProtocolType::Tcp));
+        assert!(!manager.transactions.is_pending());
+    }
+
+    #[test]
+    fn poll_fails_on_closed_stream() {
+        let items = vec![config_item(ModbusStorage::AI, 1, "temperature", 100)];
+        let mut manager = ModbusManager::new(&items, ProtocolType::Tcp, 0).unwrap();
+        let mut stream = MockStream::new(vec![]);
+        assert!(manager.poll(&mut stream).is_err());
     }
 }
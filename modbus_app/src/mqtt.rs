@@ -0,0 +1,276 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rumqttc::{Client, Event, Packet, QoS, SubscribeFilter};
+use serde_json::{json, Number, Value as Json};
+
+use crate::{
+    config_manager::{
+        modbus_variables::{ConfigItem, ModbusRequestItems},
+        mqtt_config::{MqttConfig, STATUS_OFFLINE, STATUS_ONLINE},
+    },
+    connection::{ConnectionState, StateChange},
+    decoder::Value,
+    error::ModbusError,
+    export::{Export, WriteRequest},
+    modbus_manager::PollVariable,
+};
+
+/// Размер очереди исходящих сообщений клиента
+const QUEUE_CAPACITY: usize = 100;
+
+/// Публикация значений переменных канала в брокер MQTT.
+///
+/// Значения публикуются после каждого опроса в топики переменных, состояние
+/// канала - в топик состояния с флагом retain. Сообщения из топиков
+/// переменных с суффиксом /set передаются циклу опроса как запросы записи
+/// сразу по получении.
+pub struct MqttExport {
+    client: Client,
+    config: MqttConfig,
+    channel: String,
+    qos: QoS,
+    /// Последнее состояние канала, повторно публикуется после переподключения к брокеру
+    status: Arc<Mutex<&'static str>>,
+    /// Очередь клиента переполнена, сообщения не публикуются
+    overflow: bool,
+}
+
+impl MqttExport {
+    /// Подключается к брокеру от имени канала и подписывается на запись его
+    /// переменных, запросы записи отправляются в writes
+    pub fn start(
+        config: &MqttConfig,
+        channel: &str,
+        items: &[ConfigItem],
+        writes: Sender<WriteRequest>,
+    ) -> Result<Self, ModbusError> {
+        let qos = config.qos()?;
+        let (client, mut connection) = Client::new(config.options(channel)?, QUEUE_CAPACITY);
+        let set_topics = items
+            .iter()
+            .map(|item| {
+                (
+                    format!("{}/set", config.topic(channel, &item.name)),
+                    item.name.to_owned(),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let status = Arc::new(Mutex::new(STATUS_OFFLINE));
+        let export = Self {
+            client: client.to_owned(),
+            config: config.to_owned(),
+            channel: channel.to_owned(),
+            qos,
+            status: status.to_owned(),
+            overflow: false,
+        };
+        let status_topic = config.status_topic(channel);
+        let channel = channel.to_owned();
+        std::thread::spawn(move || {
+            for event in connection.iter() {
+                match event {
+                    // Подписки и состояние восстанавливаются после каждого подключения
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        let filters = set_topics
+                            .keys()
+                            .map(|topic| SubscribeFilter::new(topic.to_owned(), qos));
+                        let status = *status.lock().unwrap_or_else(PoisonError::into_inner);
+                        if let Err(err) = client
+                            .try_subscribe_many(filters)
+                            .and_then(|_| client.try_publish(&status_topic, qos, true, status))
+                        {
+                            println!("Канал {channel}: ошибка MQTT: {err}");
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        if let Some(name) = set_topics.get(&publish.topic) {
+                            if writes
+                                .send((name.to_owned(), set_value(&publish.payload)))
+                                .is_err()
+                            {
+                                break;
+                            }
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        println!("Канал {channel}: ошибка MQTT: {err}");
+                        std::thread::sleep(Duration::from_secs(1));
+                    }
+                }
+            }
+        });
+        Ok(export)
+    }
+
+    /// Получатель событий соединения, публикующий состояние канала
+    pub fn status_events(&self) -> Sender<StateChange> {
+        let (sender, receiver) = mpsc::channel::<StateChange>();
+        let client = self.client.to_owned();
+        let topic = self.config.status_topic(&self.channel);
+        let qos = self.qos;
+        let status = self.status.to_owned();
+        std::thread::spawn(move || {
+            for event in receiver {
+                let state = match event.to {
+                    ConnectionState::Connected => STATUS_ONLINE,
+                    ConnectionState::Disconnected | ConnectionState::Backoff => STATUS_OFFLINE,
+                    ConnectionState::Connecting => continue,
+                };
+                let mut status = status.lock().unwrap_or_else(PoisonError::into_inner);
+                if *status != state {
+                    *status = state;
+                    if let Err(err) = client.try_publish(&topic, qos, true, state) {
+                        println!("Канал {}: ошибка MQTT: {err}", event.channel);
+                    }
+                }
+            }
+        });
+        sender
+    }
+}
+
+impl Export for MqttExport {
+    fn publish(&mut self, variables: &[&PollVariable]) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        for variable in variables {
+            let topic = self.config.topic(&self.channel, &variable.item().name);
+            let result = self.client.try_publish(
+                topic,
+                self.qos,
+                self.config.retain(),
+                payload(
+                    variable.item(),
+                    variable.value().as_ref(),
+                    variable.error().as_deref(),
+                    timestamp,
+                ),
+            );
+            // Пока брокер недоступен, очередь переполняется на каждом опросе,
+            // поэтому сообщается только начало и конец переполнения
+            match result {
+                Err(err) if !self.overflow => {
+                    self.overflow = true;
+                    println!(
+                        "Канал {}: значения не публикуются в MQTT: {err}",
+                        self.channel
+                    );
+                }
+                Ok(()) if self.overflow => {
+                    self.overflow = false;
+                    println!("Канал {}: публикация в MQTT возобновлена", self.channel);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Сообщение со значением переменной: value, quality, timestamp в
+/// миллисекундах Unix и, если заданы, unit и error
+fn payload(
+    item: &ModbusRequestItems,
+    value: Option<&Value>,
+    error: Option<&str>,
+    timestamp: u64,
+) -> String {
+    let mut payload = json!({
+        "value": value
+            .map(|value| json_value(value, item.precision))
+            .unwrap_or(Json::Null),
+        "quality": if value.is_some() { "good" } else { "bad" },
+        "timestamp": timestamp,
+    });
+    if let Some(unit) = &item.unit {
+        payload["unit"] = json!(unit);
+    }
+    if let Some(error) = error {
+        payload["error"] = json!(error);
+    }
+    payload.to_string()
+}
+
+/// Значение переменной в JSON, вещественные числа округляются до precision
+fn json_value(value: &Value, precision: Option<usize>) -> Json {
+    match value {
+        Value::Bool(value) => json!(value),
+        Value::I16(value) => json!(value),
+        Value::U16(value) => json!(value),
+        Value::I32(value) => json!(value),
+        Value::U32(value) => json!(value),
+        Value::I64(value) => json!(value),
+        Value::U64(value) => json!(value),
+        // Число берется из текстового представления, чтобы f32 не получил
+        // лишних знаков при переводе в f64. NaN и бесконечность - null
+        Value::F32(_) | Value::F64(_) => value
+            .format(precision, None)
+            .parse()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Json::Number)
+            .unwrap_or(Json::Null),
+        Value::String(value) => json!(value),
+    }
+}
+
+/// Записываемое значение из сообщения /set: текст, строка JSON или объект
+/// с полем value
+fn set_value(payload: &[u8]) -> String {
+    let text = String::from_utf8_lossy(payload).trim().to_string();
+    match serde_json::from_str(&text) {
+        Ok(Json::String(value)) => value,
+        Ok(Json::Object(mut object)) => match object.remove("value") {
+            Some(Json::String(value)) => value,
+            Some(value) => value.to_string(),
+            None => text,
+        },
+        _ => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_contains_value_and_quality() {
+        assert_eq!(json_value(&Value::F32(12.3), None), json!(12.3));
+        assert_eq!(json_value(&Value::F64(1.23456), Some(2)), json!(1.23));
+        assert_eq!(json_value(&Value::F32(f32::NAN), None), Json::Null);
+        assert_eq!(json_value(&Value::Bool(true), None), json!(true));
+        let item: ConfigItem = serde_yaml::from_str(
+            "{storage: ai, id: 1, unit_id: 1, name: flow, start: 0, unit: m3/h}",
+        )
+        .unwrap();
+        let item = ModbusRequestItems::try_from(item).unwrap();
+        let good: Json =
+            serde_json::from_str(&payload(&item, Some(&Value::U16(7)), None, 1000)).unwrap();
+        assert_eq!(
+            good,
+            json!({"value": 7, "quality": "good", "timestamp": 1000, "unit": "m3/h"})
+        );
+        let bad: Json = serde_json::from_str(&payload(&item, None, Some("timeout"), 1000)).unwrap();
+        assert_eq!(bad["value"], Json::Null);
+        assert_eq!(bad["quality"], json!("bad"));
+        assert_eq!(bad["error"], json!("timeout"));
+    }
+
+    #[test]
+    fn set_payload_formats() {
+        assert_eq!(set_value(b" 12.5 "), "12.5");
+        assert_eq!(set_value(b"\"on\""), "on");
+        assert_eq!(set_value(br#"{"value": 3}"#), "3");
+        assert_eq!(set_value(br#"{"value": "true"}"#), "true");
+        assert_eq!(set_value(b"{broken"), "{broken");
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::config_manager::modbus_variables::{ModbusRequestItems, ModbusStorage};
+use crate::task::{ProtocolType, Task};
+
+/// Положение переменной в ответе на блочный запрос
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockVariable {
+    /// Индекс переменной в конфигурации
+    pub index: usize,
+    /// Смещение первого регистра переменной от начала блока
+    pub offset: u16,
+    pub count: u16,
+}
+
+/// Запрос чтения непрерывного диапазона адресов, общий для нескольких переменных
+pub struct PollBlock {
+    pub task: Task,
+    pub variables: Vec<BlockVariable>,
+    /// Интервал опроса, общий для всех переменных блока
+    pub interval: Duration,
+}
+
+impl PollBlock {
+    /// Раскладывает прочитанные данные блока по переменным
+    pub fn scatter<'a>(
+        &'a self,
+        data: &'a [u16],
+    ) -> impl Iterator<Item = (usize, Option<Vec<u16>>)> + 'a {
+        self.variables.iter().map(|variable| {
+            let start = variable.offset as usize;
+            let end = start + variable.count as usize;
+            (
+                variable.index,
+                data.get(start..end).map(|value| value.to_vec()),
+            )
+        })
+    }
+}
+
+/// Собираемый блок: начальный адрес, адрес за последним регистром и переменные
+struct BlockBuilder {
+    id: u16,
+    start: u16,
+    end: u32,
+    variables: Vec<(usize, u16, u16)>,
+}
+
+/// Группирует переменные с одинаковыми unit_id, областью памяти и интервалом
+/// опроса в блочные запросы.
+///
+/// Переменные объединяются, если разрыв между ними не превышает max_gap адресов,
+/// а размер блока не превышает ограничение протокола для области памяти.
+pub fn plan(items: &[ModbusRequestItems], protocol: ProtocolType, max_gap: u16) -> Vec<PollBlock> {
+    let mut groups: BTreeMap<(u8, ModbusStorage, Duration), Vec<usize>> = BTreeMap::new();
+    for (index, item) in items.iter().enumerate() {
+        groups
+            .entry((item.unit_id, item.storage, item.poll_interval))
+            .or_default()
+            .push(index);
+    }
+    let mut blocks = Vec::new();
+    for ((unit_id, storage, interval), mut indexes) in groups {
+        indexes.sort_by_key(|&index| items[index].start);
+        let limit = storage.max_count() as u32;
+        let finish = |builder: BlockBuilder| PollBlock {
+            task: Task::new(
+                builder.id,
+                unit_id,
+                protocol.to_owned(),
+                storage.read_command(),
+                builder.start,
+                (builder.end - builder.start as u32) as u16,
+                vec![],
+            ),
+            variables: builder
+                .variables
+                .into_iter()
+                .map(|(index, start, count)| BlockVariable {
+                    index,
+                    offset: start - builder.start,
+                    count,
+                })
+                .collect(),
+            interval,
+        };
+        let mut current: Option<BlockBuilder> = None;
+        for index in indexes {
+            let item = &items[index];
+            let item_end = item.start as u32 + item.count as u32;
+            if let Some(builder) = current.as_mut() {
+                let end = builder.end.max(item_end);
+                if item.start as u32 <= builder.end + max_gap as u32
+                    && end - builder.start as u32 <= limit
+                {
+                    builder.end = end;
+                    builder.variables.push((index, item.start, item.count));
+                    continue;
+                }
+            }
+            if let Some(builder) = current.take() {
+                blocks.push(finish(builder));
+            }
+            current = Some(BlockBuilder {
+                id: item.id,
+                start: item.start,
+                end: item_end,
+                variables: vec![(index, item.start, item.count)],
+            });
+        }
+        if let Some(builder) = current {
+            blocks.push(finish(builder));
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::modbus_variables::{ByteOrder, DataType, DEFAULT_POLL_INTERVAL};
+
+    fn item(storage: ModbusStorage, unit_id: u8, start: u16) -> ModbusRequestItems {
+        ModbusRequestItems {
+            storage,
+            id: start,
+            unit_id,
+            name: format!("var_{start}"),
+            start,
+            count: 1,
+            data_type: DataType::U16,
+            byte_order: ByteOrder::Abcd,
+            scaling: None,
+            unit: None,
+            precision: None,
+            bit_mask: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    fn ranges(blocks: &[PollBlock]) -> Vec<(u16, u16)> {
+        blocks
+            .iter()
+            .map(|block| (block.task.start(), block.task.count()))
+            .collect()
+    }
+
+    #[test]
+    fn plan_merges_contiguous_variables() {
+        let items = vec![
+            item(ModbusStorage::AO, 1, 12),
+            item(ModbusStorage::AO, 1, 10),
+            item(ModbusStorage::AO, 1, 11),
+            item(ModbusStorage::AO, 1, 20),
+        ];
+        let blocks = plan(&items, ProtocolType::Tcp, 0);
+        assert_eq!(ranges(&blocks), vec![(10, 3), (20, 1)]);
+        let offsets = blocks[0]
+            .variables
+            .iter()
+            .map(|variable| (variable.index, variable.offset))
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, vec![(1, 0), (2, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn plan_merges_within_gap() {
+        let items = vec![
+            item(ModbusStorage::AI, 1, 10),
+            item(ModbusStorage::AI, 1, 14),
+            item(ModbusStorage::AI, 1, 30),
+        ];
+        assert_eq!(
+            ranges(&plan(&items, ProtocolType::Tcp, 3)),
+            vec![(10, 5), (30, 1)]
+        );
+        assert_eq!(
+            ranges(&plan(&items, ProtocolType::Tcp, 2)),
+            vec![(10, 1), (14, 1), (30, 1)]
+        );
+    }
+
+    #[test]
+    fn plan_separates_units_and_storages() {
+        let items = vec![
+            item(ModbusStorage::AO, 1, 0),
+            item(ModbusStorage::AO, 2, 1),
+            item(ModbusStorage::AI, 1, 1),
+            item(ModbusStorage::DO, 1, 1),
+        ];
+        assert_eq!(plan(&items, ProtocolType::Tcp, 10).len(), 4);
+    }
+
+    #[test]
+    fn plan_separates_poll_intervals() {
+        let fast = ModbusRequestItems {
+            poll_interval: Duration::from_millis(100),
+            ..item(ModbusStorage::AI, 1, 1)
+        };
+        let items = vec![item(ModbusStorage::AI, 1, 0), fast];
+        let blocks = plan(&items, ProtocolType::Tcp, 10);
+        assert_eq!(ranges(&blocks), vec![(1, 1), (0, 1)]);
+        assert_eq!(blocks[0].interval, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn plan_respects_protocol_limits() {
+        let registers = (0..=125)
+            .map(|start| item(ModbusStorage::AO, 1, start))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranges(&plan(&registers, ProtocolType::Tcp, 0)),
+            vec![(0, 125), (125, 1)]
+        );
+        let coils = vec![
+            item(ModbusStorage::DO, 1, 0),
+            item(ModbusStorage::DO, 1, 1999),
+        ];
+        assert_eq!(
+            ranges(&plan(&coils, ProtocolType::Tcp, 2000)),
+            vec![(0, 2000)]
+        );
+    }
+
+    #[test]
+    fn scatter_returns_variable_values() {
+        let items = vec![item(ModbusStorage::AO, 1, 4), item(ModbusStorage::AO, 1, 6)];
+        let blocks = plan(&items, ProtocolType::Tcp, 1);
+        let values = blocks[0].scatter(&[7, 8, 9]).collect::<Vec<_>>();
+        assert_eq!(values, vec![(0, Some(vec![7])), (1, Some(vec![9]))]);
+        let values = blocks[0].scatter(&[7]).collect::<Vec<_>>();
+        assert_eq!(values, vec![(0, Some(vec![7])), (1, None)]);
+    }
+}
@@ -0,0 +1,237 @@
+use std::io::{Read, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::config_manager::channel_config::ChannelConfig;
+use crate::error::{ExceptionCode, ModbusError};
+use crate::modbus_manager::exchange;
+use crate::task::{CommandType, ProtocolType, Task};
+
+/// Запрос, которым проверяется наличие устройства
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Probe {
+    /// Чтение регистра хранения 0 (FC 3)
+    Holding,
+    /// Чтение идентификатора устройства (FC 17), только для последовательной линии
+    ServerId,
+}
+
+impl FromStr for Probe {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match &value.to_lowercase()[..] {
+            "holding" | "3" => Ok(Probe::Holding),
+            "server-id" | "server_id" | "17" => Ok(Probe::ServerId),
+            _ => Err(format!(
+                "неизвестный запрос {value}, ожидается holding или server-id"
+            )),
+        }
+    }
+}
+
+impl Probe {
+    fn task(&self, id: u16, unit_id: u8, protocol: ProtocolType) -> Task {
+        match self {
+            Probe::Holding => Task::new(
+                id,
+                unit_id,
+                protocol,
+                CommandType::ReadHoldingRegisters,
+                0,
+                1,
+                vec![],
+            ),
+            Probe::ServerId => Task::new(
+                id,
+                unit_id,
+                protocol,
+                CommandType::ReportServerId,
+                0,
+                0,
+                vec![],
+            ),
+        }
+    }
+}
+
+/// Результат опроса одного адреса
+#[derive(Debug)]
+pub enum Reply {
+    /// Устройство ответило данными
+    Data,
+    /// Устройство ответило исключением, но оно есть на линии
+    Exception(ExceptionCode),
+    /// Ответа нет
+    Timeout,
+    /// Ответ не разобран, например при нескольких устройствах с одним адресом,
+    /// или ошибка канала связи
+    Error(ModbusError),
+}
+
+/// Опрашивает один адрес устройства
+pub fn probe<S: Read + Write>(
+    stream: &mut S,
+    protocol: ProtocolType,
+    probe: Probe,
+    unit_id: u8,
+) -> Reply {
+    let mut task = probe.task(unit_id as u16, unit_id, protocol);
+    match exchange(stream, &mut task) {
+        Ok(_) => Reply::Data,
+        // Шлюз отвечает этими исключениями за отсутствующее устройство
+        Err(err) => match err.error {
+            ModbusError::Exception(ExceptionCode(0x0A | 0x0B)) | ModbusError::Timeout => {
+                Reply::Timeout
+            }
+            ModbusError::Exception(code) => Reply::Exception(code),
+            error => Reply::Error(error),
+        },
+    }
+}
+
+/// Список адресов с объединением соседних в диапазоны: 1-5, 7, 9-10
+fn ranges(units: &[u8]) -> String {
+    let mut ranges: Vec<(u8, u8)> = vec![];
+    for &unit in units {
+        match ranges.last_mut() {
+            Some((_, end)) if *end as u16 + 1 == unit as u16 => *end = unit,
+            _ => ranges.push((unit, unit)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{start}-{end}"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Опрашивает адреса устройств from..=to на канале из конфигурации
+/// с таймаутом timeout секунд
+pub fn run(
+    channel: &ChannelConfig,
+    from: u8,
+    to: u8,
+    probe_with: Probe,
+    timeout: f64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if from == 0 || from > to || to > 247 {
+        Err(ModbusError::Config(format!(
+            "недопустимый диапазон адресов {from}-{to}, ожидаются адреса 1..247"
+        )))?;
+    }
+    if probe_with == Probe::ServerId && channel.protocol_type() != ProtocolType::Uart {
+        Err(ModbusError::Config(
+            "запрос server-id доступен только на последовательной линии".to_string(),
+        ))?;
+    }
+    if timeout <= 0.0 || Duration::try_from_secs_f64(timeout).is_err() {
+        Err(ModbusError::Config(format!(
+            "недопустимое время ожидания {timeout}, ожидается положительное число секунд"
+        )))?;
+    }
+    let mut channel = channel.to_owned();
+    channel.timeout = Some(timeout);
+    let protocol = channel.protocol_type();
+    let mut stream = channel.open()?;
+    let mut silent = vec![];
+    let mut responded = 0;
+    for unit_id in from..=to {
+        let reply = probe(&mut stream, protocol.to_owned(), probe_with, unit_id);
+        match &reply {
+            Reply::Data => println!("{unit_id}: ответ получен"),
+            Reply::Exception(code) => println!("{unit_id}: исключение {code}"),
+            Reply::Timeout => silent.push(unit_id),
+            Reply::Error(err) => println!("{unit_id}: ошибка ответа: {err}"),
+        }
+        if !matches!(
+            reply,
+            Reply::Timeout | Reply::Error(ModbusError::Transport(_))
+        ) {
+            responded += 1;
+        }
+        // После таймаута в Modbus TCP поздний ответ остался бы в соединении
+        let broken = match &reply {
+            Reply::Timeout => ModbusError::Timeout.breaks_connection(&protocol),
+            Reply::Error(err) => err.breaks_connection(&protocol),
+            _ => false,
+        };
+        if broken && unit_id < to {
+            stream = channel.open()?;
+        }
+    }
+    println!(
+        "Ответили адреса: {responded} из {}",
+        (to - from) as usize + 1
+    );
+    if !silent.is_empty() {
+        println!("Не ответили: {}", ranges(&silent));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockStream;
+
+    #[test]
+    fn probe_replies() {
+        let input = [
+            ProtocolType::Uart.encode_frame(0, &[0x01, 0x03, 0x02, 0x00, 0x07]),
+            ProtocolType::Uart.encode_frame(0, &[0x02, 0x83, 0x02]),
+            ProtocolType::Uart.encode_frame(0, &[0x03, 0x11, 0x02, 0x2A, 0xFF]),
+        ]
+        .concat();
+        let mut stream = MockStream::silent(input);
+        let protocol = ProtocolType::Uart;
+        assert!(matches!(
+            probe(&mut stream, protocol.to_owned(), Probe::Holding, 1),
+            Reply::Data
+        ));
+        assert!(matches!(
+            probe(&mut stream, protocol.to_owned(), Probe::Holding, 2),
+            Reply::Exception(ExceptionCode(0x02))
+        ));
+        assert!(matches!(
+            probe(&mut stream, protocol.to_owned(), Probe::ServerId, 3),
+            Reply::Data
+        ));
+        assert!(matches!(
+            probe(&mut stream, protocol.to_owned(), Probe::Holding, 4),
+            Reply::Timeout
+        ));
+        assert_eq!(
+            &stream.output[..8],
+            &[0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0A]
+        );
+        assert_eq!(&stream.output[16..20], &[0x03, 0x11, 0xC1, 0x4C]);
+    }
+
+    #[test]
+    fn scan_arguments_are_checked() {
+        use crate::cmd::Args;
+        use clap::Parser;
+
+        for args in [
+            vec!["modbus_app", "scan", "--to", "248"],
+            vec!["modbus_app", "scan", "--from", "0"],
+        ] {
+            assert!(Args::try_parse_from(&args).is_err(), "{args:?}");
+        }
+        assert!(Args::try_parse_from(["modbus_app", "scan", "--from", "247"]).is_ok());
+        let tcp: ChannelConfig = serde_yaml::from_str("{host: 127.0.0.1, port: 1}").unwrap();
+        let err = run(&tcp, 1, 2, Probe::ServerId, 0.1).unwrap_err();
+        assert!(err.to_string().contains("server-id"), "{err}");
+    }
+
+    #[test]
+    fn silent_units_are_grouped() {
+        assert_eq!(ranges(&[1, 2, 3, 5, 7, 8, 247]), "1-3, 5, 7-8, 247");
+        assert_eq!(ranges(&[254, 255]), "254-255");
+        assert_eq!(ranges(&[]), "");
+    }
+}
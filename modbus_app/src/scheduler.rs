@@ -0,0 +1,110 @@
+use std::time::{Duration, Instant};
+
+/// Срок очередного выполнения периодической задачи
+struct Entry {
+    interval: Duration,
+    deadline: Instant,
+}
+
+/// Планировщик периодических задач по срокам выполнения.
+///
+/// Из наступивших задач первой выполняется задача с наименьшим интервалом,
+/// при равных интервалах - с более ранним сроком. Поэтому медленные задачи
+/// задерживают быстрые не более чем на время выполнения одной задачи.
+pub struct Scheduler {
+    entries: Vec<Entry>,
+}
+
+impl Scheduler {
+    /// Создает планировщик, в котором все задачи наступают в момент now
+    pub fn new(intervals: impl IntoIterator<Item = Duration>, now: Instant) -> Self {
+        Self {
+            entries: intervals
+                .into_iter()
+                .map(|interval| Entry {
+                    interval,
+                    deadline: now,
+                })
+                .collect(),
+        }
+    }
+
+    /// Назначает всем задачам срок now, например после восстановления связи
+    pub fn restart(&mut self, now: Instant) {
+        for entry in self.entries.iter_mut() {
+            entry.deadline = now;
+        }
+    }
+
+    /// Индексы задач, срок которых наступил к моменту now, в порядке выполнения
+    pub fn due(&self, now: Instant) -> Vec<usize> {
+        let mut due = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.deadline <= now)
+            .collect::<Vec<_>>();
+        due.sort_by_key(|(_, entry)| (entry.interval, entry.deadline));
+        due.into_iter().map(|(index, _)| index).collect()
+    }
+
+    /// Ближайший срок выполнения среди всех задач
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.entries.iter().map(|entry| entry.deadline).min()
+    }
+
+    /// Отмечает выполнение задачи к моменту now и назначает следующий срок.
+    ///
+    /// Сроки следуют с шагом интервала без накопления задержек. Если задача
+    /// пропустила следующий срок, возвращается опоздание, а отсчет начинается
+    /// заново от момента выполнения.
+    pub fn complete(&mut self, index: usize, now: Instant) -> Option<Duration> {
+        let entry = &mut self.entries[index];
+        let next = entry.deadline + entry.interval;
+        if next > now {
+            entry.deadline = next;
+            return None;
+        }
+        let late = now - entry.deadline;
+        entry.deadline = now + entry.interval;
+        Some(late)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fast_tasks_go_first() {
+        let now = Instant::now();
+        let scheduler = Scheduler::new([Duration::from_secs(60), Duration::from_millis(100)], now);
+        assert_eq!(scheduler.due(now), vec![1, 0]);
+    }
+
+    #[test]
+    fn deadlines_keep_fixed_rate() {
+        let now = Instant::now();
+        let interval = Duration::from_millis(100);
+        let mut scheduler = Scheduler::new([interval, Duration::from_secs(60)], now);
+        assert_eq!(scheduler.complete(0, now + Duration::from_millis(30)), None);
+        assert_eq!(scheduler.due(now + Duration::from_millis(30)), vec![1]);
+        assert_eq!(scheduler.complete(1, now + Duration::from_millis(40)), None);
+        assert_eq!(
+            scheduler.due(now + Duration::from_millis(99)),
+            Vec::<usize>::new()
+        );
+        assert_eq!(scheduler.next_deadline(), Some(now + interval));
+        assert_eq!(scheduler.due(now + interval), vec![0]);
+    }
+
+    #[test]
+    fn overrun_is_reported() {
+        let now = Instant::now();
+        let interval = Duration::from_millis(100);
+        let mut scheduler = Scheduler::new([interval], now);
+        let late = Duration::from_millis(250);
+        assert_eq!(scheduler.complete(0, now + late), Some(late));
+        assert_eq!(scheduler.next_deadline(), Some(now + late + interval));
+    }
+}
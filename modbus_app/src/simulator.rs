@@ -0,0 +1,221 @@
+use rmodbus::{
+    server::{context::ModbusContextFull, ModbusFrame},
+    ErrorKind, ModbusFrameBuf, ModbusProto,
+};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::config_manager::simulator_config::SimulatorConfig;
+
+/// Встроенный Modbus TCP сервер для проверки клиента без оборудования
+#[derive(Clone)]
+pub struct Simulator {
+    unit_id: u8,
+    context: Arc<RwLock<ModbusContextFull>>,
+}
+
+impl Simulator {
+    pub fn new(config: &SimulatorConfig) -> Result<Self, ErrorKind> {
+        let mut context = ModbusContextFull::new();
+        for block in &config.coils {
+            context.set_coils_bulk(block.start, &block.values)?;
+        }
+        for block in &config.discrete_inputs {
+            context.set_discretes_bulk(block.start, &block.values)?;
+        }
+        for block in &config.holding_registers {
+            context.set_holdings_bulk(block.start, &block.values)?;
+        }
+        for block in &config.input_registers {
+            context.set_inputs_bulk(block.start, &block.values)?;
+        }
+        Ok(Self {
+            unit_id: config.unit_id.unwrap_or(1),
+            context: Arc::new(RwLock::new(context)),
+        })
+    }
+
+    /// Принимает подключения клиентов, каждый клиент обслуживается в своем потоке
+    pub fn serve(&self, listener: TcpListener) -> std::io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let unit_id = self.unit_id;
+            let context = self.context.clone();
+            std::thread::spawn(move || {
+                let peer = stream
+                    .peer_addr()
+                    .map(|addr| addr.to_string())
+                    .unwrap_or_default();
+                if let Err(err) = handle_client(stream, unit_id, &context) {
+                    println!("Ошибка обмена с клиентом {peer}: {err}");
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Обрабатывает запросы одного клиента до закрытия соединения
+fn handle_client(
+    mut stream: TcpStream,
+    unit_id: u8,
+    context: &RwLock<ModbusContextFull>,
+) -> std::io::Result<()> {
+    loop {
+        let mut buf: ModbusFrameBuf = [0; 256];
+        match stream.read_exact(&mut buf[..6]) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        let len = u16::from_be_bytes([buf[4], buf[5]]) as usize;
+        if !(2..=250).contains(&len) {
+            return Err(invalid_data(ErrorKind::FrameBroken));
+        }
+        stream.read_exact(&mut buf[6..6 + len])?;
+        let mut response = Vec::new();
+        let mut frame = ModbusFrame::new(unit_id, &buf, ModbusProto::TcpUdp, &mut response);
+        frame.parse().map_err(invalid_data)?;
+        if frame.processing_required {
+            let result = if frame.readonly {
+                frame.process_read(&context.read().unwrap_or_else(PoisonError::into_inner))
+            } else {
+                frame.process_write(&mut context.write().unwrap_or_else(PoisonError::into_inner))
+            };
+            result.map_err(invalid_data)?;
+        }
+        if frame.response_required {
+            frame.finalize_response().map_err(invalid_data)?;
+            stream.write_all(&response)?;
+        }
+    }
+}
+
+fn invalid_data(err: ErrorKind) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+}
+
+/// Запускает симулятор с начальными значениями из файла
+pub fn run(listen: &str, data: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let config = match data {
+        Some(path) => SimulatorConfig::try_read_config_file(path)?,
+        None => SimulatorConfig::default(),
+    };
+    let simulator = Simulator::new(&config)?;
+    let listener = TcpListener::bind(listen)?;
+    println!("Симулятор ожидает подключений: {}", listener.local_addr()?);
+    simulator.serve(listener)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::modbus_variables::ModbusStorage;
+    use crate::error::{ExceptionCode, ModbusError};
+    use crate::modbus_manager::exchange;
+    use crate::task::{CommandType, ProtocolType, Task};
+    use std::time::Duration;
+
+    const CONFIG: &str = "
+unit_id: 1
+coils:
+  - start: 0
+    values: [true, false, true]
+discrete_inputs:
+  - start: 10
+    values: [true]
+holding_registers:
+  - start: 100
+    values: [555, 100]
+input_registers:
+  - start: 7
+    values: [42]
+";
+
+    fn start_simulator() -> Result<(Simulator, TcpStream), Box<dyn std::error::Error>> {
+        let config: SimulatorConfig = serde_yaml::from_str(CONFIG)?;
+        let simulator = Simulator::new(&config)?;
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let server = simulator.clone();
+        std::thread::spawn(move || server.serve(listener));
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+        Ok((simulator, stream))
+    }
+
+    fn read_task(storage: ModbusStorage, start: u16) -> Task {
+        let command = storage.read_command();
+        Task::new(1, 1, ProtocolType::Tcp, command, start, 1, vec![])
+    }
+
+    #[test]
+    fn simulator_serves_initial_values() -> Result<(), Box<dyn std::error::Error>> {
+        let (_simulator, mut stream) = start_simulator()?;
+        let cases = [
+            (ModbusStorage::DO, 2, 1),
+            (ModbusStorage::DI, 10, 1),
+            (ModbusStorage::AO, 101, 100),
+            (ModbusStorage::AI, 7, 42),
+        ];
+        for (storage, start, expected) in cases {
+            let mut task = read_task(storage, start);
+            assert_eq!(exchange(&mut stream, &mut task)?, Some(vec![expected]));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn simulator_accepts_writes() -> Result<(), Box<dyn std::error::Error>> {
+        let (simulator, mut stream) = start_simulator()?;
+        let mut task = Task::new(
+            2,
+            1,
+            ProtocolType::Tcp,
+            CommandType::PresetSingleRegister,
+            5,
+            1,
+            vec![0x55FF],
+        );
+        assert_eq!(exchange(&mut stream, &mut task)?, None);
+        let context = simulator
+            .context
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        assert_eq!(context.get_holding(5)?, 0x55FF);
+        Ok(())
+    }
+
+    #[test]
+    fn simulator_reports_illegal_address() -> Result<(), Box<dyn std::error::Error>> {
+        let (_simulator, mut stream) = start_simulator()?;
+        let mut task = read_task(ModbusStorage::AI, 20000);
+        let err = exchange(&mut stream, &mut task).unwrap_err();
+        assert!(matches!(
+            err.error,
+            ModbusError::Exception(ExceptionCode(0x02))
+        ));
+        assert_eq!(err.context.function, 0x04);
+        Ok(())
+    }
+
+    #[test]
+    fn simulator_rejects_custom_function() -> Result<(), Box<dyn std::error::Error>> {
+        let (_simulator, mut stream) = start_simulator()?;
+        let command = CommandType::Custom {
+            function: 0x41,
+            payload: vec![0x01, 0x02, 0x03, 0x04],
+        };
+        let mut task = Task::new(3, 1, ProtocolType::Tcp, command, 0, 0, vec![]);
+        let err = exchange(&mut stream, &mut task).unwrap_err();
+        assert!(matches!(
+            err.error,
+            ModbusError::Exception(ExceptionCode(0x01))
+        ));
+        assert_eq!(err.context.function, 0x41);
+        Ok(())
+    }
+}
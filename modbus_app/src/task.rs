@@ -1,59 +1,68 @@
-//use std::{assert_matches::debug_assert_matches, os::unix::fs::FileTypeExt};
-
-use alloc::task;
+use getset::{CopyGetters, Getters, Setters};
 use rmodbus::{client::ModbusRequest, guess_response_frame_len, ErrorKind, ModbusProto};
-use serde::{Deserialize, Serialize};
-use std::io::Read;
-use std::{fs::File, path::PathBuf};
+use serde::Deserialize;
+use std::str::FromStr;
+
+use crate::error::TaskContext;
 
+#[derive(Getters, CopyGetters, Setters)]
 pub struct Task {
+    /// Номер транзакции Modbus TCP
+    #[getset(get_copy = "pub", set = "pub")]
     id: u16,
+    #[getset(get_copy = "pub")]
     unit_id: u8,
+    #[getset(get = "pub")]
     protocol: ProtocolType,
+    #[getset(get = "pub")]
     command: CommandType,
+    #[getset(get_copy = "pub")]
     start: u16,
+    #[getset(get_copy = "pub")]
     count: u16,
     data: Vec<u16>,
     mreq: Option<ModbusRequest>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
-pub struct Variables {
-    pub storage: String,
-    pub id: u16,
-    pub unit_id: u8,
-    pub name: u16,
-    pub start: u16,
-}
-
-impl Variables {
-    pub fn from_variables_to_task(&self) -> Task {
-        let task = Task {
-            id: self.id,
-            unit_id: self.unit_id,
-            protocol: ProtocolType::Tcp,
-            command: CommandType::ReadCoilStatus,
-            start: self.start,
-            count: 2,
-            data: vec![],
-            mreq: None,
-        };
-    }
+/// Протокол обмена. В конфигурации допускаются имена tcp, uart и rtu без учета регистра
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(try_from = "String")]
+pub enum ProtocolType {
+    Tcp,
+    Uart,
 }
 
-let variables = Variables {
+impl FromStr for ProtocolType {
+    type Err = String;
 
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match &value.to_lowercase()[..] {
+            "tcp" => Ok(ProtocolType::Tcp),
+            "uart" | "rtu" => Ok(ProtocolType::Uart),
+            _ => Err(format!(
+                "неизвестный протокол {value}, ожидается Tcp или Uart (Rtu)"
+            )),
+        }
+    }
 }
 
-let task = Task.from_variables_to_task();
+impl TryFrom<String> for ProtocolType {
+    type Error = String;
 
-#[derive(Debug, Deserialize, Clone)]
-enum ProtocolType {
-    Tcp,
-    Uart,
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
 }
 
-enum CommandType {
+/// Функции Modbus. Функции 1-6, 15 и 16 формируются и разбираются rmodbus,
+/// остальные - вручную.
+///
+/// Ответы на функции, возвращающие байты, а не регистры (7, 12, 17, 43/14),
+/// передаются по одному байту в элементе результата.
+#[derive(Debug, Clone, PartialEq)]
+// Не все функции используются опросом, часть нужна только для обслуживания устройств
+#[allow(dead_code)]
+pub enum CommandType {
     ReadCoilStatus,
     ReadInputStatus,
     ReadHoldingRegisters,
@@ -62,17 +71,205 @@ enum CommandType {
     PresetSingleRegister,
     ForceMultipleCoils,
     PresetMultipleRegisters,
+    /// FC 7, результат - байт состояния
+    ReadExceptionStatus,
+    /// FC 8, в запросе и ответе - данные подфункции, результат - слова данных ответа
+    Diagnostics {
+        sub_function: u16,
+    },
+    /// FC 11, результат - слово состояния и счетчик событий
+    GetCommEventCounter,
+    /// FC 12, результат - слово состояния, счетчики событий и сообщений
+    /// и байты журнала событий
+    GetCommEventLog,
+    /// FC 17, результат - байты идентификатора, признака работы и дополнительных данных
+    ReportServerId,
+    /// FC 22, данные запроса - маски AND и OR регистра start
+    MaskWriteRegister,
+    /// FC 23, читает count регистров с start и записывает данные запроса с write_start
+    ReadWriteMultipleRegisters {
+        write_start: u16,
+    },
+    /// FC 43/14, код чтения (1 - основные, 2 - обычные, 3 - расширенные,
+    /// 4 - один объект) и номер первого объекта. Результат - байты ответа после
+    /// кода чтения: уровень соответствия, признак продолжения, номер следующего
+    /// объекта, количество объектов и объекты (номер, длина, значение)
+    ReadDeviceIdentification {
+        code: u8,
+        object_id: u8,
+    },
+    /// Функция производителя (например 65-72, 100-110) с произвольными данными
+    /// запроса. Результат - байты ответа после кода функции.
+    ///
+    /// Длина ответа RTU на функцию с неизвестным форматом ответа заранее
+    /// неизвестна, поэтому кадр дочитывается по байту, пока не сойдется
+    /// контрольная сумма.
+    Custom {
+        function: u8,
+        payload: Vec<u8>,
+    },
 }
 
-impl Into<ModbusProto> for ProtocolType {
-    fn into(self) -> ModbusProto {
-        match &self {
+impl From<ProtocolType> for ModbusProto {
+    fn from(value: ProtocolType) -> Self {
+        match value {
             ProtocolType::Tcp => ModbusProto::TcpUdp,
             ProtocolType::Uart => ModbusProto::Rtu,
         }
     }
 }
 
+impl ProtocolType {
+    /// Количество байт ответа, достаточное для определения длины кадра
+    pub fn head_len(&self) -> usize {
+        match self {
+            ProtocolType::Tcp => 6,
+            ProtocolType::Uart => 3,
+        }
+    }
+
+    /// Смещение адреса устройства от начала кадра
+    pub fn frame_start(&self) -> usize {
+        match self {
+            ProtocolType::Tcp => 6,
+            ProtocolType::Uart => 0,
+        }
+    }
+
+    /// Оборачивает адрес устройства и PDU в кадр протокола: заголовок MBAP
+    /// с номером транзакции id для TCP, контрольную сумму для RTU
+    pub fn encode_frame(&self, id: u16, body: &[u8]) -> Vec<u8> {
+        match self {
+            ProtocolType::Tcp => {
+                let mut frame = Vec::with_capacity(body.len() + 6);
+                frame.extend(id.to_be_bytes());
+                frame.extend([0, 0]);
+                frame.extend((body.len() as u16).to_be_bytes());
+                frame.extend(body);
+                frame
+            }
+            ProtocolType::Uart => {
+                let mut frame = body.to_vec();
+                frame.extend(crc16(body).to_le_bytes());
+                frame
+            }
+        }
+    }
+
+    /// Адрес устройства и PDU из кадра протокола после проверки заголовка
+    /// MBAP или контрольной суммы
+    pub fn decode_frame<'a>(&self, frame: &'a [u8]) -> Result<&'a [u8], ErrorKind> {
+        match self {
+            ProtocolType::Tcp => match frame {
+                [_, _, 0, 0, len_hi, len_lo, body @ ..]
+                    if body.len() >= 2
+                        && u16::from_be_bytes([*len_hi, *len_lo]) as usize == body.len() =>
+                {
+                    Ok(body)
+                }
+                _ => Err(ErrorKind::FrameBroken),
+            },
+            ProtocolType::Uart => {
+                if frame.len() < 4 {
+                    return Err(ErrorKind::FrameBroken);
+                }
+                let (body, crc) = frame.split_at(frame.len() - 2);
+                if crc16(body).to_le_bytes() != crc {
+                    return Err(ErrorKind::FrameCRCError);
+                }
+                Ok(body)
+            }
+        }
+    }
+}
+
+/// Контрольная сумма CRC-16/MODBUS кадра RTU
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u16, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+impl CommandType {
+    /// Функция формируется и разбирается rmodbus. Custom разбирается вручную,
+    /// даже если код функции совпадает со стандартным
+    fn is_standard(&self) -> bool {
+        matches!(
+            self,
+            CommandType::ReadCoilStatus
+                | CommandType::ReadInputStatus
+                | CommandType::ReadHoldingRegisters
+                | CommandType::ReadInputRegisters
+                | CommandType::ForceSingleCoil
+                | CommandType::PresetSingleRegister
+                | CommandType::ForceMultipleCoils
+                | CommandType::PresetMultipleRegisters
+        )
+    }
+
+    /// Код функции modbus
+    pub fn function_code(&self) -> u8 {
+        match self {
+            CommandType::ReadCoilStatus => 0x01,
+            CommandType::ReadInputStatus => 0x02,
+            CommandType::ReadHoldingRegisters => 0x03,
+            CommandType::ReadInputRegisters => 0x04,
+            CommandType::ForceSingleCoil => 0x05,
+            CommandType::PresetSingleRegister => 0x06,
+            CommandType::ForceMultipleCoils => 0x0F,
+            CommandType::PresetMultipleRegisters => 0x10,
+            CommandType::ReadExceptionStatus => 0x07,
+            CommandType::Diagnostics { .. } => 0x08,
+            CommandType::GetCommEventCounter => 0x0B,
+            CommandType::GetCommEventLog => 0x0C,
+            CommandType::ReportServerId => 0x11,
+            CommandType::MaskWriteRegister => 0x16,
+            CommandType::ReadWriteMultipleRegisters { .. } => 0x17,
+            CommandType::ReadDeviceIdentification { .. } => 0x2B,
+            CommandType::Custom { function, .. } => *function,
+        }
+    }
+}
+
+impl Task {
+    pub fn new(
+        id: u16,
+        unit_id: u8,
+        protocol: ProtocolType,
+        command: CommandType,
+        start: u16,
+        count: u16,
+        data: Vec<u16>,
+    ) -> Self {
+        Self {
+            id,
+            unit_id,
+            protocol,
+            command,
+            start,
+            count,
+            data,
+            mreq: None,
+        }
+    }
+
+    /// Описание запроса для сообщений об ошибках
+    pub fn context(&self) -> TaskContext {
+        TaskContext {
+            unit_id: self.unit_id,
+            function: self.command.function_code(),
+            start: self.start,
+            count: self.count,
+        }
+    }
+}
+
 impl Task {
     pub fn generate_request(&mut self) -> Result<Vec<u8>, rmodbus::ErrorKind> {
         let mut mreq = ModbusRequest::new(self.unit_id, self.protocol.to_owned().into());
@@ -92,15 +289,11 @@ impl Task {
             CommandType::ReadInputRegisters => {
                 mreq.generate_get_inputs(self.start, self.count, &mut request)?;
             }
-            CommandType::ForceSingleCoil => match self.data.get(0) {
-                Some(data) => mreq.generate_set_coil(
-                    self.start,
-                    if data == &0 { false } else { true },
-                    &mut request,
-                )?,
+            CommandType::ForceSingleCoil => match self.data.first() {
+                Some(data) => mreq.generate_set_coil(self.start, data != &0, &mut request)?,
                 None => Err(ErrorKind::IllegalDataValue)?,
             },
-            CommandType::PresetSingleRegister => match self.data.get(0) {
+            CommandType::PresetSingleRegister => match self.data.first() {
                 Some(data) => {
                     mreq.generate_set_holding(self.start, data.to_owned(), &mut request)?;
                 }
@@ -124,16 +317,58 @@ impl Task {
                     return Err(ErrorKind::IllegalDataValue)?;
                 }
             }
+            _ => {
+                let mut body = vec![self.unit_id, self.command.function_code()];
+                body.extend(self.request_data()?);
+                self.mreq = None;
+                return Ok(self.protocol.encode_frame(self.id, &body));
+            }
         }
         self.mreq = Some(mreq);
         Ok(request)
     }
+
+    /// Данные запроса после кода функции для функций, не поддерживаемых rmodbus
+    fn request_data(&self) -> Result<Vec<u8>, ErrorKind> {
+        let words = |words: &[u16]| words.iter().flat_map(|word| word.to_be_bytes()).collect();
+        Ok(match &self.command {
+            CommandType::Diagnostics { sub_function } => {
+                words(&[&[*sub_function], &self.data[..]].concat())
+            }
+            CommandType::MaskWriteRegister => match self.data[..] {
+                [and_mask, or_mask] => words(&[self.start, and_mask, or_mask]),
+                _ => Err(ErrorKind::IllegalDataValue)?,
+            },
+            CommandType::ReadWriteMultipleRegisters { write_start } => {
+                if !(1..=125).contains(&self.count) || !(1..=121).contains(&self.data.len()) {
+                    Err(ErrorKind::IllegalDataValue)?;
+                }
+                let mut data: Vec<u8> =
+                    words(&[self.start, self.count, *write_start, self.data.len() as u16]);
+                data.push(self.data.len() as u8 * 2);
+                data.extend(words(&self.data));
+                data
+            }
+            CommandType::ReadDeviceIdentification { code, object_id } => {
+                vec![MEI_DEVICE_IDENTIFICATION, *code, *object_id]
+            }
+            // PDU не длиннее 253 байт, старший бит кода функции означает исключение
+            CommandType::Custom { function, payload } => {
+                if !(1..0x80).contains(function) || payload.len() > 252 {
+                    Err(ErrorKind::IllegalDataValue)?;
+                }
+                payload.to_owned()
+            }
+            _ => vec![],
+        })
+    }
 }
 
+/// Тип MEI запроса чтения идентификации устройства (FC 43/14)
+const MEI_DEVICE_IDENTIFICATION: u8 = 0x0E;
+
 #[cfg(test)]
 mod tests {
-    use std::{array, result};
-
     use super::*;
     #[test]
     fn tcp_read_coil() -> Result<(), ErrorKind> {
@@ -399,6 +634,112 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn rtu_diagnostic_requests() -> Result<(), ErrorKind> {
+        let cases: [(CommandType, Vec<u16>, &[u8]); 5] = [
+            (
+                CommandType::ReadExceptionStatus,
+                vec![],
+                &[0x11, 0x07, 0x4C, 0x22],
+            ),
+            (
+                CommandType::Diagnostics { sub_function: 0 },
+                vec![0xA537],
+                &[0x11, 0x08, 0x00, 0x00, 0xA5, 0x37, 0xD8, 0x1D],
+            ),
+            (
+                CommandType::GetCommEventCounter,
+                vec![],
+                &[0x11, 0x0B, 0x4C, 0x27],
+            ),
+            (
+                CommandType::GetCommEventLog,
+                vec![],
+                &[0x11, 0x0C, 0x0D, 0xE5],
+            ),
+            (
+                CommandType::ReportServerId,
+                vec![],
+                &[0x11, 0x11, 0xCD, 0xEC],
+            ),
+        ];
+        for (command, data, frame) in cases {
+            let mut task = Task::new(1, 17, ProtocolType::Uart, command, 0, 0, data);
+            assert_eq!(task.generate_request()?, frame);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn tcp_mask_write_register() -> Result<(), ErrorKind> {
+        let mut task = Task::new(
+            1,
+            1,
+            ProtocolType::Tcp,
+            CommandType::MaskWriteRegister,
+            4,
+            1,
+            vec![0x00F2, 0x0025],
+        );
+        assert_eq!(
+            task.generate_request()?,
+            [0, 0x01, 0x00, 0x00, 0x00, 0x08, 0x01, 0x16, 0x00, 0x04, 0x00, 0xF2, 0x00, 0x25]
+        );
+        task = Task::new(
+            1,
+            1,
+            ProtocolType::Tcp,
+            CommandType::MaskWriteRegister,
+            4,
+            1,
+            vec![0x00F2],
+        );
+        assert_eq!(task.generate_request(), Err(ErrorKind::IllegalDataValue));
+        Ok(())
+    }
+
+    #[test]
+    fn tcp_read_write_multiple_registers() -> Result<(), ErrorKind> {
+        let mut task = Task::new(
+            1,
+            1,
+            ProtocolType::Tcp,
+            CommandType::ReadWriteMultipleRegisters { write_start: 0x0E },
+            3,
+            6,
+            vec![0x00FF, 0x00FF, 0x00FF],
+        );
+        assert_eq!(
+            task.generate_request()?,
+            [
+                0, 0x01, 0x00, 0x00, 0x00, 0x11, 0x01, 0x17, 0x00, 0x03, 0x00, 0x06, 0x00, 0x0E,
+                0x00, 0x03, 0x06, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tcp_read_device_identification() -> Result<(), ErrorKind> {
+        let mut task = Task::new(
+            1,
+            1,
+            ProtocolType::Tcp,
+            CommandType::ReadDeviceIdentification {
+                code: 1,
+                object_id: 0,
+            },
+            0,
+            0,
+            vec![],
+        );
+        assert_eq!(
+            task.generate_request()?,
+            [0, 0x01, 0x00, 0x00, 0x00, 0x05, 0x01, 0x2B, 0x0E, 0x01, 0x00]
+        );
+        Ok(())
+    }
+
     // #[test]
     // fn rtu_force_multiple_coils() -> Result<(), ErrorKind> {
     //     let mut task_seven_rtu = Task {
@@ -438,14 +779,75 @@ mod tests {
 }
 
 impl Task {
-    pub fn get_responce_len(&self, data: &[u8]) -> Result<u8, ErrorKind> {
-        Ok(guess_response_frame_len(
-            data,
-            self.protocol.to_owned().into(),
-        )?)
+    /// Длина кадра ответа по уже принятым байтам, начиная с head_len байт.
+    ///
+    /// Длину ответа на FC 43/14 по RTU можно узнать только по мере приема
+    /// объектов, поэтому, пока возвращаемая длина больше числа принятых байт,
+    /// недостающие байты дочитываются и функция вызывается снова.
+    pub fn get_responce_len(&self, data: &[u8]) -> Result<usize, ErrorKind> {
+        match (&self.protocol, data) {
+            // Длина из заголовка MBAP, кадр может быть длиннее 255 байт
+            (ProtocolType::Tcp, [_, _, 0, 0, len_hi, len_lo, ..]) => {
+                return Ok(6 + u16::from_be_bytes([*len_hi, *len_lo]) as usize)
+            }
+            (ProtocolType::Tcp, _) => return Err(ErrorKind::FrameBroken),
+            (ProtocolType::Uart, _) => {}
+        }
+        let mut request = vec![self.command.function_code()];
+        request.extend(self.request_data()?);
+        rtu_response_len(&request, data)
     }
 }
 
+/// Длина кадра ответа RTU на запрос с PDU request (код функции и данные)
+/// по принятому началу кадра data. Длина некоторых ответов уточняется по
+/// мере приема, поэтому кадр дочитывается, пока она превышает принятую часть
+pub fn rtu_response_len(request: &[u8], data: &[u8]) -> Result<usize, ErrorKind> {
+    let (Some(function), Some(response_function)) = (request.first(), data.get(1)) else {
+        return Err(ErrorKind::FrameBroken);
+    };
+    if response_function & 0x80 != 0 {
+        return Ok(5);
+    }
+    // Адрес устройства, код функции, данные и контрольная сумма
+    let frame_len = |data_len: usize| data_len + 4;
+    let byte_count = || data.get(2).map(|&count| count as usize + 1);
+    Ok(match function {
+        0x01..=0x06 | 0x0F | 0x10 => guess_response_frame_len(data, ModbusProto::Rtu)? as usize,
+        0x07 => frame_len(1),
+        // Ответ повторяет подфункцию и данные запроса
+        0x08 => frame_len(request.len() - 1),
+        0x0B => frame_len(4),
+        0x16 => frame_len(6),
+        0x0C | 0x11 | 0x17 => frame_len(byte_count().ok_or(ErrorKind::FrameBroken)?),
+        0x2B if request.get(1) == Some(&MEI_DEVICE_IDENTIFICATION) => {
+            // Заголовок: тип MEI, код чтения, уровень соответствия,
+            // признак продолжения, следующий объект, количество объектов
+            let mut len = 2 + 6;
+            if let Some(&count) = data.get(len - 1) {
+                for _ in 0..count {
+                    match data.get(len + 1) {
+                        Some(&object_len) => len += 2 + object_len as usize,
+                        None => return Ok(len + 2),
+                    }
+                }
+            }
+            len + 2
+        }
+        // Формат ответа неизвестен: кадр читается по байту, пока не сойдется
+        // контрольная сумма
+        _ => {
+            if data.len() >= 4 && ProtocolType::Uart.decode_frame(data).is_ok() {
+                data.len()
+            } else if data.len() < 256 {
+                data.len() + 1
+            } else {
+                Err(ErrorKind::FrameBroken)?
+            }
+        }
+    })
+}
+
 impl Task {
     pub fn show_result(
         &self,
@@ -455,6 +857,9 @@ impl Task {
         // обработка ошибки
         let mut data = Vec::from(head_arr);
         data.extend(tail_arr);
+        if !self.command.is_standard() {
+            return self.parse_manual(&data);
+        }
         let res = match &self.mreq {
             Some(mreq) => match &self.command {
                 CommandType::ReadCoilStatus | CommandType::ReadInputStatus => {
@@ -477,20 +882,140 @@ impl Task {
                     mreq.parse_ok(&data)?;
                     return Ok(None);
                 }
+                _ => return self.parse_manual(&data),
             },
             None => Err(ErrorKind::Acknowledge)?,
         };
         Ok(Some(res))
     }
+
+    /// Разбирает ответ на функцию, не поддерживаемую rmodbus
+    fn parse_manual(&self, frame: &[u8]) -> Result<Option<Vec<u16>>, ErrorKind> {
+        let data = self.response_data(frame)?;
+        let bytes = |bytes: &[u8]| bytes.iter().map(|&byte| byte as u16).collect::<Vec<_>>();
+        let words = |bytes: &[u8]| {
+            bytes
+                .chunks_exact(2)
+                .map(|word| u16::from_be_bytes([word[0], word[1]]))
+                .collect::<Vec<_>>()
+        };
+        // Данные после счетчика байт, счетчик должен совпадать с их длиной
+        let counted = |min: usize| match data {
+            [count, rest @ ..] if *count as usize == rest.len() && rest.len() >= min => Ok(rest),
+            _ => Err(ErrorKind::FrameBroken),
+        };
+        Ok(Some(match &self.command {
+            CommandType::ReadExceptionStatus => match data {
+                [status] => vec![*status as u16],
+                _ => Err(ErrorKind::FrameBroken)?,
+            },
+            CommandType::Diagnostics { sub_function } => match data {
+                [hi, lo, rest @ ..]
+                    if u16::from_be_bytes([*hi, *lo]) == *sub_function && rest.len() % 2 == 0 =>
+                {
+                    words(rest)
+                }
+                _ => Err(ErrorKind::FrameBroken)?,
+            },
+            CommandType::GetCommEventCounter => match data {
+                [_, _, _, _] => words(data),
+                _ => Err(ErrorKind::FrameBroken)?,
+            },
+            CommandType::GetCommEventLog => {
+                let log = counted(6)?;
+                [words(&log[..6]), bytes(&log[6..])].concat()
+            }
+            CommandType::ReportServerId => bytes(counted(1)?),
+            CommandType::MaskWriteRegister => {
+                if data != self.request_data()? {
+                    Err(ErrorKind::FrameBroken)?;
+                }
+                return Ok(None);
+            }
+            CommandType::ReadWriteMultipleRegisters { .. } => {
+                let registers = counted(0)?;
+                if registers.len() != self.count as usize * 2 {
+                    Err(ErrorKind::FrameBroken)?;
+                }
+                words(registers)
+            }
+            CommandType::ReadDeviceIdentification { code, .. } => match data {
+                [MEI_DEVICE_IDENTIFICATION, response_code, rest @ ..]
+                    if response_code == code && DeviceIdentification::parse(rest).is_some() =>
+                {
+                    bytes(rest)
+                }
+                _ => Err(ErrorKind::FrameBroken)?,
+            },
+            CommandType::Custom { .. } => bytes(data),
+            _ => Err(ErrorKind::IllegalFunction)?,
+        }))
+    }
+
+    /// Данные ответа после кода функции. Проверяет кадр, номер транзакции,
+    /// адрес устройства и код функции, ответ-исключение возвращается ошибкой
+    fn response_data<'a>(&self, frame: &'a [u8]) -> Result<&'a [u8], ErrorKind> {
+        let body = self.protocol.decode_frame(frame)?;
+        if self.protocol == ProtocolType::Tcp && frame[..2] != self.id.to_be_bytes() {
+            return Err(ErrorKind::FrameBroken);
+        }
+        let function = self.command.function_code();
+        match body {
+            [unit, response, data @ ..] if *unit == self.unit_id && *response == function => {
+                Ok(data)
+            }
+            [unit, response, code, ..] if *unit == self.unit_id && *response == function | 0x80 => {
+                Err(ErrorKind::from_modbus_error(*code))
+            }
+            _ => Err(ErrorKind::FrameBroken),
+        }
+    }
+}
+
+/// Ответ на чтение идентификации устройства (FC 43/14)
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceIdentification {
+    /// Уровень соответствия устройства
+    pub conformity: u8,
+    /// Объекты не поместились в ответ, чтение продолжается с next_object_id
+    pub more_follows: bool,
+    pub next_object_id: u8,
+    /// Номера и значения объектов
+    pub objects: Vec<(u8, Vec<u8>)>,
+}
+
+impl DeviceIdentification {
+    /// Разбирает байты ответа после кода чтения: уровень соответствия, признак
+    /// продолжения, номер следующего объекта, количество объектов и объекты.
+    /// None, если длины объектов не сходятся с длиной ответа
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let [conformity, more_follows, next_object_id, count, objects @ ..] = data else {
+            return None;
+        };
+        let mut rest = objects;
+        let mut objects = vec![];
+        for _ in 0..*count {
+            match rest {
+                [id, len, tail @ ..] if tail.len() >= *len as usize => {
+                    let (value, tail) = tail.split_at(*len as usize);
+                    objects.push((*id, value.to_vec()));
+                    rest = tail;
+                }
+                _ => return None,
+            }
+        }
+        rest.is_empty().then_some(Self {
+            conformity: *conformity,
+            more_follows: *more_follows == 0xFF,
+            next_object_id: *next_object_id,
+            objects,
+        })
+    }
 }
 
 #[cfg(test)]
-use std::io::{stdout, Write};
 mod tests_two {
-    use clap::error;
-
     use super::*;
-    use std::result;
     #[test]
     fn test_show_result_read_coil_status() -> Result<(), ErrorKind> {
         let mut task_one = Task {
@@ -508,7 +1033,7 @@ mod tests_two {
         task_one.generate_request()?;
         let result_one = task_one.show_result(&head_arr, &tail_arr)?;
         println!("result_one: {:?}", result_one);
-        assert_eq!(result_one, Some(vec![0 as u16, 1 as u16]));
+        assert_eq!(result_one, Some(vec![0_u16, 1_u16]));
         Ok(())
     }
 
@@ -529,7 +1054,7 @@ mod tests_two {
         task_two.generate_request()?;
         let result_two = task_two.show_result(&head_arr, &tail_arr)?;
         println!("result_two: {:?}", result_two);
-        assert_eq!(&result_two, &Some(vec![1 as u16, 1 as u16]));
+        assert_eq!(&result_two, &Some(vec![1_u16, 1_u16]));
         Ok(())
     }
 
@@ -550,7 +1075,7 @@ mod tests_two {
         task_three.generate_request()?;
         let result_three = task_three.show_result(&head_arr, &tail_arr)?;
         println!("result_three: {:?}", result_three);
-        assert_eq!(result_three, Some(vec![10 as u16, 100 as u16]));
+        assert_eq!(result_three, Some(vec![10_u16, 100_u16]));
         Ok(())
     }
 
@@ -571,7 +1096,7 @@ mod tests_two {
         task_four.generate_request()?;
         let result_four = task_four.show_result(&head_arr, &tail_arr)?;
         println!("result_four: {:?}", result_four);
-        assert_eq!(result_four, Some(vec![555 as u16, 100 as u16]));
+        assert_eq!(result_four, Some(vec![555_u16, 100_u16]));
         Ok(())
     }
 
@@ -658,4 +1183,278 @@ mod tests_two {
         assert_eq!(result_eight, None);
         Ok(())
     }
+
+    #[test]
+    fn frames_convert_between_protocols() -> Result<(), ErrorKind> {
+        let tcp = [
+            0x12, 0x34, 0x00, 0x00, 0x00, 0x06, 0x01, 0x03, 0x00, 0x0A, 0x00, 0x01,
+        ];
+        let body = ProtocolType::Tcp.decode_frame(&tcp)?;
+        let rtu = ProtocolType::Uart.encode_frame(0, body);
+        assert_eq!(rtu, vec![0x01, 0x03, 0x00, 0x0A, 0x00, 0x01, 0xA4, 0x08]);
+        assert_eq!(ProtocolType::Uart.decode_frame(&rtu)?, body);
+        assert_eq!(ProtocolType::Tcp.encode_frame(0x1234, body), tcp.to_vec());
+        assert_eq!(
+            ProtocolType::Uart.decode_frame(&[0x01, 0x03, 0x00, 0x0A, 0x00, 0x01, 0xA4, 0x09]),
+            Err(ErrorKind::FrameCRCError)
+        );
+        assert_eq!(
+            ProtocolType::Tcp.decode_frame(&tcp[..11]),
+            Err(ErrorKind::FrameBroken)
+        );
+        Ok(())
+    }
+
+    /// Длина ответа RTU по первым байтам и разбор всего кадра
+    fn rtu_response(task: &mut Task, frame: &[u8]) -> Result<Option<Vec<u16>>, ErrorKind> {
+        task.generate_request()?;
+        let head_len = task.protocol().head_len();
+        assert_eq!(task.get_responce_len(&frame[..head_len])?, frame.len());
+        task.show_result(&frame[..head_len], &frame[head_len..])
+    }
+
+    #[test]
+    fn rtu_diagnostic_responses() -> Result<(), ErrorKind> {
+        let mut task = Task::new(
+            1,
+            17,
+            ProtocolType::Uart,
+            CommandType::ReadExceptionStatus,
+            0,
+            0,
+            vec![],
+        );
+        assert_eq!(
+            rtu_response(&mut task, &[0x11, 0x07, 0x6D, 0xE2, 0x18])?,
+            Some(vec![0x6D])
+        );
+        let mut task = Task::new(
+            1,
+            17,
+            ProtocolType::Uart,
+            CommandType::Diagnostics { sub_function: 0 },
+            0,
+            0,
+            vec![0xA537],
+        );
+        let echo = [0x11, 0x08, 0x00, 0x00, 0xA5, 0x37, 0xD8, 0x1D];
+        assert_eq!(rtu_response(&mut task, &echo)?, Some(vec![0xA537]));
+        let other = [0x11, 0x08, 0x00, 0x01, 0xA5, 0x37, 0x89, 0xDD];
+        assert_eq!(rtu_response(&mut task, &other), Err(ErrorKind::FrameBroken));
+        let mut task = Task::new(
+            1,
+            17,
+            ProtocolType::Uart,
+            CommandType::GetCommEventLog,
+            0,
+            0,
+            vec![],
+        );
+        let log = [
+            0x11, 0x0C, 0x08, 0x00, 0x00, 0x01, 0x08, 0x01, 0x21, 0x20, 0x00, 0x59, 0x01,
+        ];
+        assert_eq!(
+            rtu_response(&mut task, &log)?,
+            Some(vec![0x0000, 0x0108, 0x0121, 0x20, 0x00])
+        );
+        let mut task = Task::new(
+            1,
+            17,
+            ProtocolType::Uart,
+            CommandType::ReportServerId,
+            0,
+            0,
+            vec![],
+        );
+        let server_id = [0x11, 0x11, 0x03, 0x0A, 0xFF, 0x42, 0x1E, 0xDE];
+        assert_eq!(
+            rtu_response(&mut task, &server_id)?,
+            Some(vec![0x0A, 0xFF, 0x42])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tcp_register_function_responses() -> Result<(), ErrorKind> {
+        let mut task = Task::new(
+            1,
+            1,
+            ProtocolType::Tcp,
+            CommandType::GetCommEventCounter,
+            0,
+            0,
+            vec![],
+        );
+        task.generate_request()?;
+        let head = [0x00, 0x01, 0x00, 0x00, 0x00, 0x06];
+        assert_eq!(task.get_responce_len(&head)?, 12);
+        assert_eq!(
+            task.show_result(&head, &[0x01, 0x0B, 0xFF, 0xFF, 0x01, 0x08])?,
+            Some(vec![0xFFFF, 0x0108])
+        );
+        let mut task = Task::new(
+            1,
+            1,
+            ProtocolType::Tcp,
+            CommandType::MaskWriteRegister,
+            4,
+            1,
+            vec![0x00F2, 0x0025],
+        );
+        task.generate_request()?;
+        let head = [0x00, 0x01, 0x00, 0x00, 0x00, 0x08];
+        let echo = [0x01, 0x16, 0x00, 0x04, 0x00, 0xF2, 0x00, 0x25];
+        assert_eq!(task.show_result(&head, &echo)?, None);
+        let mut task = Task::new(
+            1,
+            1,
+            ProtocolType::Tcp,
+            CommandType::ReadWriteMultipleRegisters { write_start: 0x0E },
+            3,
+            6,
+            vec![0x00FF, 0x00FF, 0x00FF],
+        );
+        task.generate_request()?;
+        let head = [0x00, 0x01, 0x00, 0x00, 0x00, 0x0F];
+        let registers = [
+            0x01, 0x17, 0x0C, 0x00, 0xFE, 0x0A, 0xCD, 0x00, 0x01, 0x00, 0x03, 0x00, 0x0D, 0x00,
+            0xFF,
+        ];
+        assert_eq!(
+            task.show_result(&head, &registers)?,
+            Some(vec![0x00FE, 0x0ACD, 0x0001, 0x0003, 0x000D, 0x00FF])
+        );
+        // Ответ на другую транзакцию не принимается
+        let head = [0x00, 0x02, 0x00, 0x00, 0x00, 0x0F];
+        assert_eq!(
+            task.show_result(&head, &registers),
+            Err(ErrorKind::FrameBroken)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rtu_device_identification_response() -> Result<(), ErrorKind> {
+        let mut task = Task::new(
+            1,
+            17,
+            ProtocolType::Uart,
+            CommandType::ReadDeviceIdentification {
+                code: 1,
+                object_id: 0,
+            },
+            0,
+            0,
+            vec![],
+        );
+        task.generate_request()?;
+        let frame = [
+            0x11, 0x2B, 0x0E, 0x01, 0x01, 0x00, 0x00, 0x03, 0x00, 0x03, 0x41, 0x42, 0x43, 0x01,
+            0x02, 0x50, 0x31, 0x02, 0x03, 0x31, 0x2E, 0x30, 0xF9, 0xF2,
+        ];
+        // Длина уточняется по мере приема объектов
+        let mut received = task.protocol().head_len();
+        let mut lengths = vec![];
+        loop {
+            let len = task.get_responce_len(&frame[..received])?;
+            if len <= received {
+                break;
+            }
+            lengths.push(len);
+            received = len;
+        }
+        assert_eq!(lengths, vec![10, 15, 19, 24]);
+        assert_eq!(
+            task.show_result(&frame[..3], &frame[3..])?,
+            Some(
+                frame[4..22]
+                    .iter()
+                    .map(|&byte| byte as u16)
+                    .collect::<Vec<_>>()
+            )
+        );
+        let exception = [0x11, 0xAB, 0x01, 0x9F, 0x35];
+        assert_eq!(task.get_responce_len(&exception[..3])?, 5);
+        assert_eq!(
+            task.show_result(&exception[..3], &exception[3..]),
+            Err(ErrorKind::IllegalFunction)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn custom_function_passthrough() -> Result<(), ErrorKind> {
+        let command = CommandType::Custom {
+            function: 0x41,
+            payload: vec![0x01, 0x02],
+        };
+        let mut task = Task::new(7, 1, ProtocolType::Tcp, command.to_owned(), 0, 0, vec![]);
+        assert_eq!(
+            task.generate_request()?,
+            [0x00, 0x07, 0x00, 0x00, 0x00, 0x04, 0x01, 0x41, 0x01, 0x02]
+        );
+        let mut task = Task::new(1, 17, ProtocolType::Uart, command, 0, 0, vec![]);
+        assert_eq!(
+            task.generate_request()?,
+            [0x11, 0x41, 0x01, 0x02, 0xD5, 0x5D]
+        );
+        // Кадр дочитывается по байту до совпадения контрольной суммы
+        let frame = [0x11, 0x41, 0xAA, 0xBB, 0xCC, 0x9E, 0xBA];
+        let mut received = task.protocol().head_len();
+        while task.get_responce_len(&frame[..received])? > received {
+            received += 1;
+        }
+        assert_eq!(received, frame.len());
+        assert_eq!(
+            task.show_result(&frame[..3], &frame[3..])?,
+            Some(vec![0xAA, 0xBB, 0xCC])
+        );
+        let exception = [0x11, 0xC1, 0x01, 0xB1, 0x95];
+        assert_eq!(task.get_responce_len(&exception[..3])?, 5);
+        assert_eq!(
+            task.show_result(&exception[..3], &exception[3..]),
+            Err(ErrorKind::IllegalFunction)
+        );
+        let mut task = Task::new(
+            1,
+            17,
+            ProtocolType::Uart,
+            CommandType::Custom {
+                function: 0x90,
+                payload: vec![],
+            },
+            0,
+            0,
+            vec![],
+        );
+        assert_eq!(task.generate_request(), Err(ErrorKind::IllegalDataValue));
+        Ok(())
+    }
+
+    #[test]
+    fn custom_function_with_standard_code() -> Result<(), ErrorKind> {
+        let command = CommandType::Custom {
+            function: 0x03,
+            payload: vec![0x00, 0x0A, 0x00, 0x01],
+        };
+        let mut task = Task::new(9, 1, ProtocolType::Tcp, command.to_owned(), 0, 0, vec![]);
+        task.generate_request()?;
+        // Ответ разбирается как ответ функции производителя, а не rmodbus
+        let frame = [
+            0x00, 0x09, 0x00, 0x00, 0x00, 0x05, 0x01, 0x03, 0x02, 0x01, 0x2C,
+        ];
+        assert_eq!(
+            task.show_result(&frame[..6], &frame[6..])?,
+            Some(vec![0x02, 0x01, 0x2C])
+        );
+        let mut task = Task::new(1, 1, ProtocolType::Uart, command, 0, 0, vec![]);
+        task.generate_request()?;
+        let frame = [0x01, 0x03, 0x02, 0x01, 0x2C, 0xB8, 0x09];
+        assert_eq!(task.get_responce_len(&frame[..3])?, frame.len());
+        assert_eq!(
+            task.show_result(&frame[..3], &frame[3..])?,
+            Some(vec![0x02, 0x01, 0x2C])
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+/// Номера транзакций Modbus TCP в пределах одного соединения.
+///
+/// Номер выдается на каждый отправленный запрос и не повторяется, пока
+/// ответ на запрос не получен, поэтому ответы можно сопоставлять запросам
+/// независимо от порядка их прихода.
+#[derive(Default)]
+pub struct TransactionManager {
+    next_id: u16,
+    /// Ожидающие ответа транзакции и индексы их блоков опроса
+    pending: HashMap<u16, usize>,
+}
+
+impl TransactionManager {
+    /// Выдает номер транзакции для запроса блока
+    pub fn allocate(&mut self, block: usize) -> u16 {
+        while self.pending.contains_key(&self.next_id) {
+            self.next_id = self.next_id.wrapping_add(1);
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.pending.insert(id, block);
+        id
+    }
+
+    /// Завершает транзакцию по номеру из ответа, возвращает индекс блока
+    pub fn resolve(&mut self, id: u16) -> Option<usize> {
+        self.pending.remove(&id)
+    }
+
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Забывает все ожидающие транзакции, например после разрыва соединения
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_unique_while_pending() {
+        let mut transactions = TransactionManager {
+            next_id: u16::MAX,
+            ..Default::default()
+        };
+        assert_eq!(transactions.allocate(0), u16::MAX);
+        assert_eq!(transactions.allocate(1), 0);
+        transactions.next_id = u16::MAX;
+        assert_eq!(transactions.allocate(2), 1);
+        assert_eq!(transactions.resolve(0), Some(1));
+        assert_eq!(transactions.resolve(0), None);
+        assert_eq!(transactions.resolve(u16::MAX), Some(0));
+        assert!(transactions.is_pending());
+        transactions.clear();
+        assert!(!transactions.is_pending());
+    }
+}
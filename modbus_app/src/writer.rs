@@ -0,0 +1,264 @@
+use std::io::{Read, Write};
+
+use crate::config_manager::modbus_variables::{ModbusRequestItems, ModbusStorage};
+use crate::decoder::{encode_item, parse_bool};
+use crate::error::{ModbusError, TaskError};
+use crate::modbus_manager::exchange;
+#[cfg(feature = "async")]
+use crate::modbus_manager::exchange_async;
+use crate::task::{CommandType, ProtocolType, Task};
+
+/// Записывает значение переменной в устройство.
+///
+/// Битовые переменные записываются чтением регистра, изменением битов маски
+/// и записью регистра обратно. Возвращает ошибку, если устройство ответило
+/// исключением или значение не удалось закодировать.
+pub fn write_item<S: Read + Write>(
+    stream: &mut S,
+    item: &ModbusRequestItems,
+    protocol: ProtocolType,
+    text: &str,
+) -> Result<(), TaskError> {
+    let data = match bit_state(item, &protocol, text)? {
+        Some(state) => {
+            let mut read = read_task(item, &protocol);
+            let current = exchange(stream, &mut read)?.unwrap_or_default();
+            set_bit(item, &read, &current, state)?
+        }
+        None => encode(item, &protocol, text)?,
+    };
+    exchange(stream, &mut write_task(item, protocol, data)?)?;
+    Ok(())
+}
+
+/// Асинхронный вариант write_item, каждый обмен должен уложиться в timeout
+#[cfg(feature = "async")]
+pub async fn write_item_async<S>(
+    stream: &mut S,
+    item: &ModbusRequestItems,
+    protocol: ProtocolType,
+    text: &str,
+    timeout: std::time::Duration,
+) -> Result<(), TaskError>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let data = match bit_state(item, &protocol, text)? {
+        Some(state) => {
+            let mut read = read_task(item, &protocol);
+            let current = exchange_async(stream, &mut read, timeout)
+                .await?
+                .unwrap_or_default();
+            set_bit(item, &read, &current, state)?
+        }
+        None => encode(item, &protocol, text)?,
+    };
+    exchange_async(stream, &mut write_task(item, protocol, data)?, timeout).await?;
+    Ok(())
+}
+
+fn task(
+    item: &ModbusRequestItems,
+    protocol: &ProtocolType,
+    command: CommandType,
+    count: u16,
+    data: Vec<u16>,
+) -> Task {
+    Task::new(
+        item.id,
+        item.unit_id,
+        protocol.to_owned(),
+        command,
+        item.start,
+        count,
+        data,
+    )
+}
+
+/// Запрос чтения регистра битовой переменной
+fn read_task(item: &ModbusRequestItems, protocol: &ProtocolType) -> Task {
+    task(item, protocol, item.storage.read_command(), 1, vec![])
+}
+
+/// Новое состояние битов маски; None, если переменная не битовая
+fn bit_state(
+    item: &ModbusRequestItems,
+    protocol: &ProtocolType,
+    text: &str,
+) -> Result<Option<bool>, TaskError> {
+    if item.bit_mask.is_none() {
+        return Ok(None);
+    }
+    parse_bool(text).map(Some).ok_or_else(|| {
+        TaskError::new(
+            read_task(item, protocol).context(),
+            ModbusError::Decode(format!("ожидалось логическое значение, получено {text}")),
+        )
+    })
+}
+
+/// Изменяет биты маски в прочитанном значении регистра
+fn set_bit(
+    item: &ModbusRequestItems,
+    read: &Task,
+    current: &[u16],
+    state: bool,
+) -> Result<Vec<u16>, TaskError> {
+    let mask = item.bit_mask.unwrap_or_default();
+    let register = *item.byte_order.normalize(current).first().ok_or_else(|| {
+        TaskError::new(
+            read.context(),
+            ModbusError::Decode("устройство не вернуло значение регистра".to_string()),
+        )
+    })?;
+    let register = if state {
+        register | mask
+    } else {
+        register & !mask
+    };
+    Ok(item.byte_order.normalize(&[register]))
+}
+
+/// Кодирует значение переменной в регистры
+fn encode(
+    item: &ModbusRequestItems,
+    protocol: &ProtocolType,
+    text: &str,
+) -> Result<Vec<u16>, TaskError> {
+    encode_item(item, text).ok_or_else(|| {
+        TaskError::new(
+            task(
+                item,
+                protocol,
+                item.storage.read_command(),
+                item.count,
+                vec![],
+            )
+            .context(),
+            ModbusError::Decode(format!(
+                "значение {text} не соответствует типу {:?}",
+                item.data_type
+            )),
+        )
+    })
+}
+
+/// Запрос записи данных в область памяти переменной
+fn write_task(
+    item: &ModbusRequestItems,
+    protocol: ProtocolType,
+    data: Vec<u16>,
+) -> Result<Task, TaskError> {
+    let command = match (item.storage, data.len()) {
+        (ModbusStorage::DO, 1) => CommandType::ForceSingleCoil,
+        (ModbusStorage::DO, _) => CommandType::ForceMultipleCoils,
+        (ModbusStorage::AO, 1) => CommandType::PresetSingleRegister,
+        (ModbusStorage::AO, _) => CommandType::PresetMultipleRegisters,
+        (storage, _) => {
+            return Err(TaskError::new(
+                task(item, &protocol, storage.read_command(), item.count, vec![]).context(),
+                ModbusError::Config(format!(
+                    "область памяти {storage:?} доступна только для чтения"
+                )),
+            ))
+        }
+    };
+    Ok(task(item, &protocol, command, data.len() as u16, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::modbus_variables::{ByteOrder, ConfigItem, DataType};
+    use crate::config_manager::simulator_config::SimulatorConfig;
+    use crate::decoder::{decode_item, Value};
+    use crate::error::ExceptionCode;
+    use crate::simulator::Simulator;
+    use std::net::{TcpListener, TcpStream};
+
+    fn connect_simulator() -> Result<TcpStream, Box<dyn std::error::Error>> {
+        let simulator = Simulator::new(&SimulatorConfig::default())?;
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        std::thread::spawn(move || simulator.serve(listener));
+        Ok(TcpStream::connect(addr)?)
+    }
+
+    fn item(storage: &str, start: u16) -> ConfigItem {
+        serde_yaml::from_str(&format!(
+            "{{storage: {storage}, id: 1, unit_id: 1, name: test, start: {start}}}"
+        ))
+        .unwrap()
+    }
+
+    fn read_back<S: Read + Write>(
+        stream: &mut S,
+        item: &ModbusRequestItems,
+    ) -> Result<Option<Value>, Box<dyn std::error::Error>> {
+        let mut task = Task::new(
+            1,
+            item.unit_id,
+            ProtocolType::Tcp,
+            item.storage.read_command(),
+            item.start,
+            item.count,
+            vec![],
+        );
+        let registers = exchange(stream, &mut task)?.unwrap_or_default();
+        Ok(decode_item(item, &registers))
+    }
+
+    #[test]
+    fn write_typed_and_scaled_values() -> Result<(), Box<dyn std::error::Error>> {
+        let mut stream = connect_simulator()?;
+        let float = ModbusRequestItems::try_from(ConfigItem {
+            data_type: Some(DataType::F32),
+            byte_order: Some(ByteOrder::Cdab),
+            ..item("ao", 10)
+        })?;
+        write_item(&mut stream, &float, ProtocolType::Tcp, "12.5")?;
+        assert_eq!(read_back(&mut stream, &float)?, Some(Value::F32(12.5)));
+        let scaled = ModbusRequestItems::try_from(ConfigItem {
+            scale: Some(0.1),
+            ..item("ao", 20)
+        })?;
+        write_item(&mut stream, &scaled, ProtocolType::Tcp, "23.4")?;
+        let value = read_back(&mut stream, &scaled)?.map(|value| value.format(Some(1), None));
+        assert_eq!(value, Some("23.4".to_string()));
+        let coil = ModbusRequestItems::try_from(item("do", 3))?;
+        write_item(&mut stream, &coil, ProtocolType::Tcp, "true")?;
+        assert_eq!(read_back(&mut stream, &coil)?, Some(Value::Bool(true)));
+        Ok(())
+    }
+
+    #[test]
+    fn write_bit_keeps_other_bits() -> Result<(), Box<dyn std::error::Error>> {
+        let mut stream = connect_simulator()?;
+        let register = ModbusRequestItems::try_from(item("ao", 5))?;
+        write_item(&mut stream, &register, ProtocolType::Tcp, "257")?;
+        let bit = ModbusRequestItems::try_from(ConfigItem {
+            bit: Some(8),
+            ..item("ao", 5)
+        })?;
+        write_item(&mut stream, &bit, ProtocolType::Tcp, "false")?;
+        assert_eq!(read_back(&mut stream, &register)?, Some(Value::U16(1)));
+        Ok(())
+    }
+
+    #[test]
+    fn write_reports_errors() -> Result<(), Box<dyn std::error::Error>> {
+        let mut stream = connect_simulator()?;
+        let input = ModbusRequestItems::try_from(item("ai", 0))?;
+        let err = write_item(&mut stream, &input, ProtocolType::Tcp, "1").unwrap_err();
+        assert!(matches!(err.error, ModbusError::Config(_)));
+        let out_of_range = ModbusRequestItems::try_from(item("ao", 20000))?;
+        let err = write_item(&mut stream, &out_of_range, ProtocolType::Tcp, "1").unwrap_err();
+        assert!(matches!(
+            err.error,
+            ModbusError::Exception(ExceptionCode(0x02))
+        ));
+        assert_eq!(err.context.function, 0x06);
+        assert_eq!(err.context.start, 20000);
+        Ok(())
+    }
+}